@@ -0,0 +1,291 @@
+use fnv::{FnvHashMap, FnvHashSet};
+
+use std::fmt;
+use std::str::FromStr;
+
+use bulk::Bulk;
+use symbol::Sym;
+use term::{Term, TermBase};
+
+// How `[x]M` is translated into combinators, one level at a time but for Kiselyov
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Abstraction {
+	Naive,  // [x]x = I, [x]M = KM for atomic M, [x](MN) = S([x]M)([x]N)
+	KEta,   // also [x]M = KM when x is not free in M, and [x](Mx) = M
+	Turner, // also [x](MN) = BM([x]N) or C([x]M)N when x is free on one side only
+	Kiselyov, // whole binders at once with bulk combinators, in linear size. Needs B and C
+}
+impl Abstraction {
+	pub const ALL: [Abstraction; 4] = [
+		Abstraction::Naive,
+		Abstraction::KEta,
+		Abstraction::Turner,
+		Abstraction::Kiselyov,
+	];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Abstraction::Naive => "naive",
+			Abstraction::KEta => "keta",
+			Abstraction::Turner => "turner",
+			Abstraction::Kiselyov => "kiselyov",
+		}
+	}
+}
+impl fmt::Display for Abstraction {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
+impl FromStr for Abstraction {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Abstraction::ALL.iter()
+		.cloned()
+		.find(|a| a.name().eq_ignore_ascii_case(s))
+		.ok_or_else(|| format!("unknown abstraction `{}`, expected naive, keta, turner or kiselyov", s))
+	}
+}
+
+impl TermBase {
+	pub fn free_in(&self, v: Sym, key: usize) -> bool {
+		let mut seen = FnvHashSet::default();
+		let mut stack = vec![key];
+		while let Some(key) = stack.pop() {
+			if !seen.insert(key) {
+				continue;
+			}
+			match self.term(key) {
+				Term::Var(x) if x == v => return true,
+				Term::Ap(l, r) => {
+					stack.push(l);
+					stack.push(r);
+				},
+				Term::Abs(x, body) if x != v => stack.push(body),
+				Term::Lam(body) => stack.push(body),
+				_ => (),
+			}
+		}
+		false
+	}
+
+	// the variables occurring in `key`, bound or not
+	pub(crate) fn vars(&self, key: usize) -> FnvHashSet<Sym> {
+		let mut vars = FnvHashSet::default();
+		let mut seen = FnvHashSet::default();
+		let mut stack = vec![key];
+		while let Some(key) = stack.pop() {
+			if !seen.insert(key) {
+				continue;
+			}
+			match self.term(key) {
+				Term::Var(v) => {
+					vars.insert(v);
+				},
+				Term::Ap(l, r) => {
+					stack.push(l);
+					stack.push(r);
+				},
+				Term::Abs(_, body) |
+				Term::Lam(body) => stack.push(body),
+				_ => (),
+			}
+		}
+		vars
+	}
+
+	pub(crate) fn abs_can_rewrite(&self, v: Sym, body: usize) -> bool {
+		match (self.abstraction(), self.term(body)) {
+			(Abstraction::Naive, Term::Abs(_, _)) => false,
+			(Abstraction::Kiselyov, Term::Abs(_, _)) => true,
+			(_, Term::Abs(_, _)) => !self.free_in(v, body),
+			_ => true,
+		}
+	}
+
+	// one level of bracket abstraction, turning [v]body into combinators applied to smaller abstractions
+	pub(crate) fn abs_rewrite(&mut self, v: Sym, body: usize) -> usize {
+		if self.abstraction() == Abstraction::Kiselyov {
+			let key = self.find_and_ref_up(Term::Abs(v, body));
+			return self.kiselyov(key);
+		}
+		let optimize = self.abstraction() != Abstraction::Naive;
+		let q = self.term(body);
+		if q == Term::Var(v) {
+			return self.i();
+		}
+		if q.atomic() || (optimize && !self.free_in(v, body)) {
+			let k = self.k();
+			return self.ap(k, body);
+		}
+		if let Term::Ap(l, r) = q {
+			if optimize && self.term(r) == Term::Var(v) && !self.free_in(v, l) {
+				return l; // eta
+			}
+			if self.abstraction() == Abstraction::Turner && self.base().has_bc() {
+				if !self.free_in(v, l) {
+					let b = self.b();
+					let inner = self.ap(b, l);
+					let right = self.find_and_ref_up(Term::Abs(v, r));
+					return self.ap(inner, right);
+				}
+				if !self.free_in(v, r) {
+					let c = self.c();
+					let left = self.find_and_ref_up(Term::Abs(v, l));
+					let inner = self.ap(c, left);
+					return self.ap(inner, r);
+				}
+			}
+			let s = self.s();
+			let new_left = self.find_and_ref_up(Term::Abs(v, l));
+			let new_right = self.find_and_ref_up(Term::Abs(v, r));
+			let inner = self.ap(s, new_left);
+			return self.ap(inner, new_right);
+		}
+		panic!("abs_rewrite of a nested abstraction");
+	}
+
+	// eliminates every abstraction in `key` up front, so reduction only contracts combinators of the base
+	pub fn compile(&mut self, key: usize) -> usize {
+		let mut memo = FnvHashMap::default();
+		let compiled = self.compile_rec(key, &mut memo);
+		let base = self.base();
+		self.translate(compiled, base)
+	}
+
+	fn compile_rec(&mut self, key: usize, memo: &mut FnvHashMap<usize, usize>) -> usize {
+		if let Some(&k) = memo.get(&key) {
+			return k;
+		}
+		let k = match self.term(key) {
+			Term::Ap(l, r) => {
+				let l2 = self.compile_rec(l, memo);
+				let r2 = self.compile_rec(r, memo);
+				if (l2, r2) == (l, r) { key } else { self.ap(l2, r2) }
+			},
+			Term::Abs(_, _) if self.abstraction() == Abstraction::Kiselyov => self.kiselyov(key),
+			Term::Abs(v, body) => {
+				// the body is abstraction-free, so one level leaves only smaller abstractions
+				let body = self.compile_rec(body, memo);
+				let once = self.abs_rewrite(v, body);
+				self.compile_rec(once, memo)
+			},
+			_ => key,
+		};
+		memo.insert(key, k);
+		k
+	}
+
+	// Kiselyov's "Lambda to SKI, semantically" with bulk combinators.
+	// Translates a whole abstraction, nested binders included, in one go
+	fn kiselyov(&mut self, key: usize) -> usize {
+		let mut env = vec![];
+		let mut memo = FnvHashMap::default();
+		let (n, d) = self.kiselyov_rec(key, &mut env, &mut memo);
+		debug_assert_eq!(n, 0);
+		d
+	}
+
+	// `(n, d)` where `d` takes the innermost `n` bound variables, outermost first
+	fn kiselyov_rec(&mut self, key: usize, env: &mut Vec<Sym>, memo: &mut FnvHashMap<(usize, Vec<Sym>), (u32, usize)>) -> (u32, usize) {
+		if let Some(&nd) = memo.get(&(key, env.clone())) {
+			return nd;
+		}
+		let nd = match self.term(key) {
+			Term::Var(x) => match env.iter().rev().position(|&y| y == x) {
+				Some(index) => {
+					// the innermost variable is I, and each one further out ignores one more
+					let mut nd = (1, self.i());
+					for _ in 0..index {
+						let k = self.k();
+						nd = (nd.0 + 1, self.kiselyov_ap((0, k), nd));
+					}
+					nd
+				},
+				None => (0, key),
+			},
+			Term::Abs(v, body) => {
+				env.push(v);
+				let (n, d) = self.kiselyov_rec(body, env, memo);
+				env.pop();
+				if n == 0 {
+					let k = self.k();
+					(0, self.ap(k, d))
+				} else {
+					(n - 1, d)
+				}
+			},
+			Term::Ap(l, r) => {
+				let l = self.kiselyov_rec(l, env, memo);
+				let r = self.kiselyov_rec(r, env, memo);
+				(l.0.max(r.0), self.kiselyov_ap(l, r))
+			},
+			_ => (0, key),
+		};
+		memo.insert((key, env.clone()), nd);
+		nd
+	}
+
+	// applies `d1` to `d2`, passing each the variables it takes
+	fn kiselyov_ap(&mut self, (n, d1): (u32, usize), (m, d2): (u32, usize)) -> usize {
+		let (comb, first) = if n == 0 && m == 0 {
+			return self.ap(d1, d2);
+		} else if n == 0 {
+			(self.bulk(Bulk::B, m), d1)
+		} else if m == 0 {
+			(self.bulk(Bulk::C, n), d1)
+		} else if n == m {
+			(self.bulk(Bulk::S, n), d1)
+		} else if n < m {
+			let s = self.bulk(Bulk::S, n);
+			let sd1 = self.ap(s, d1);
+			(self.bulk(Bulk::B, m - n), sd1)
+		} else {
+			let b = self.bulk(Bulk::B, n - m);
+			let s = self.bulk(Bulk::S, m);
+			let bs = self.ap(b, s);
+			let bsd1 = self.ap(bs, d1);
+			(self.bulk(Bulk::C, n - m), bsd1)
+		};
+		let partial = self.ap(comb, first);
+		self.ap(partial, d2)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use reduce::{Budget, Stop};
+	use traverse::TraversalOrder;
+
+	// `d` applied to x and y, normalized
+	fn applied(tb: &mut TermBase, d: usize) -> usize {
+		let x = tb.parse("x").unwrap();
+		let y = tb.parse("y").unwrap();
+		let dx = tb.ap(d, x);
+		let dxy = tb.ap(dx, y);
+		let r = tb.normalize(dxy, TraversalOrder::LeftmostOutermost, &Budget::steps(100));
+		assert_eq!(r.stop, Stop::NormalForm);
+		r.key
+	}
+
+	#[test]
+	fn kiselyov_ap_fewer_variables_on_the_left() {
+		let mut tb = TermBase::new();
+		let f = tb.parse("f").unwrap();
+		let g = tb.parse("g").unwrap();
+		let d = tb.kiselyov_ap((1, f), (2, g));
+		let expected = tb.parse("f y (g x y)").unwrap();
+		assert_eq!(applied(&mut tb, d), expected);
+	}
+
+	#[test]
+	fn kiselyov_ap_more_variables_on_the_left() {
+		let mut tb = TermBase::new();
+		let f = tb.parse("f").unwrap();
+		let g = tb.parse("g").unwrap();
+		let d = tb.kiselyov_ap((2, f), (1, g));
+		let expected = tb.parse("f x y (g y)").unwrap();
+		assert_eq!(applied(&mut tb, d), expected);
+	}
+}
@@ -0,0 +1,225 @@
+use fnv::FnvHashMap;
+
+use std::fmt;
+use std::str::FromStr;
+
+use bulk::Bulk;
+use reduce::{Budget, Stop};
+use term::{Term, TermBase};
+use traverse::TraversalOrder;
+
+// The primitive combinators terms may be written in
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Base {
+	SKI,
+	SKIBC, // Turner's, allowing the B and C bracket abstraction rules
+	SK,    // I being SKK
+	BCKW,  // Curry's
+	Iota,  // Barker's one-point basis, X f = f S K
+}
+impl Base {
+	pub const ALL: [Base; 5] = [Base::SKI, Base::SKIBC, Base::SK, Base::BCKW, Base::Iota];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Base::SKI => "ski",
+			Base::SKIBC => "skibc",
+			Base::SK => "sk",
+			Base::BCKW => "bckw",
+			Base::Iota => "iota",
+		}
+	}
+
+	pub fn primitives(self) -> &'static [&'static str] {
+		match self {
+			Base::SKI => &["S", "K", "I"],
+			Base::SKIBC => &["S", "K", "I", "B", "C"],
+			Base::SK => &["S", "K"],
+			Base::BCKW => &["B", "C", "K", "W"],
+			Base::Iota => &["X"],
+		}
+	}
+
+	// bracket abstraction may use B and C, translating them if need be
+	pub fn has_bc(self) -> bool {
+		self == Base::SKIBC || self == Base::BCKW
+	}
+
+	// Bn, Cn and Sn are primitives
+	pub fn has_bulk(self) -> bool {
+		self == Base::SKIBC
+	}
+
+	fn has(self, t: Term) -> bool {
+		match t {
+			Term::I => self == Base::SKI || self == Base::SKIBC,
+			Term::K => self != Base::Iota,
+			Term::S => self == Base::SKI || self == Base::SKIBC || self == Base::SK,
+			Term::B |
+			Term::C => self.has_bc(),
+			Term::W => self == Base::BCKW,
+			Term::X => self == Base::Iota,
+			Term::Bulk(_, _) => self.has_bulk(),
+			_ => true, // not a primitive
+		}
+	}
+}
+impl fmt::Display for Base {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
+impl FromStr for Base {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Base::ALL.iter()
+		.cloned()
+		.find(|b| b.name().eq_ignore_ascii_case(s))
+		.ok_or_else(|| format!("unknown base `{}`, expected ski, skibc, sk, bckw or iota", s))
+	}
+}
+
+impl TermBase {
+	// `key` with every primitive outside `base` replaced by an equivalent combination of those in it
+	pub fn translate(&mut self, key: usize, base: Base) -> usize {
+		let mut memo = FnvHashMap::default();
+		self.translate_rec(key, base, &mut memo)
+	}
+
+	fn translate_rec(&mut self, key: usize, base: Base, memo: &mut FnvHashMap<usize, usize>) -> usize {
+		if let Some(&k) = memo.get(&key) {
+			return k;
+		}
+		let k = match self.term(key) {
+			Term::Ap(l, r) => {
+				let l2 = self.translate_rec(l, base, memo);
+				let r2 = self.translate_rec(r, base, memo);
+				if (l2, r2) == (l, r) { key } else { self.ap(l2, r2) }
+			},
+			Term::Abs(v, body) => {
+				let body = self.translate_rec(body, base, memo);
+				self.find_and_ref_up(Term::Abs(v, body))
+			},
+			Term::Lam(body) => {
+				let body = self.translate_rec(body, base, memo);
+				self.find_and_ref_up(Term::Lam(body))
+			},
+			t if base.has(t) => key,
+			t => {
+				// closer to `base`, so translating again ends
+				let expanded = self.expansion(t, base);
+				self.translate_rec(expanded, base, memo)
+			},
+		};
+		memo.insert(key, k);
+		k
+	}
+
+	// the primitive `t` in terms of other combinators, preferring those of `base`
+	fn expansion(&mut self, t: Term, base: Base) -> usize {
+		match (t, base) {
+			(Term::I, Base::BCKW) => self.combinators("WK"),
+			(Term::I, Base::Iota) => self.combinators("XX"),
+			(Term::I, _) => self.combinators("SKK"),
+			(Term::K, _) => self.combinators("X(X(XX))"),
+			(Term::S, Base::BCKW) => self.combinators("B(BW)(BBC)"),
+			(Term::S, _) => self.combinators("X(X(X(XX)))"),
+			(Term::B, _) => self.combinators("S(KS)K"),
+			(Term::C, _) => self.combinators("S(S(K(S(KS)K))S)(KK)"),
+			(Term::W, _) => self.combinators("SS(KI)"),
+			(Term::X, _) => self.combinators("S(SI(KS))(KK)"),
+			// Bn = B B B(n-1), Cn = B C (B C(n-1)), Sn = B S (B S(n-1))
+			(Term::Bulk(kind, n), _) => {
+				let smaller = self.bulk(kind, n - 1);
+				let (b, c, s) = (self.b(), self.c(), self.s());
+				match kind {
+					Bulk::B => {
+						let bb = self.ap(b, b);
+						self.ap(bb, smaller)
+					},
+					Bulk::C => {
+						let inner = self.ap(b, smaller);
+						let bc = self.ap(b, c);
+						self.ap(bc, inner)
+					},
+					Bulk::S => {
+						let inner = self.ap(b, smaller);
+						let bs = self.ap(b, s);
+						self.ap(bs, inner)
+					},
+				}
+			},
+			_ => panic!("no expansion of {:?}", t),
+		}
+	}
+
+	// a term of single-letter primitives and parentheses, whatever the base
+	pub(crate) fn combinators(&mut self, src: &str) -> usize {
+		let mut stack: Vec<Option<usize>> = vec![None];
+		for c in src.chars() {
+			let x = match c {
+				'(' => {
+					stack.push(None);
+					continue;
+				},
+				')' => stack.pop().unwrap().unwrap(),
+				'I' => self.i(),
+				'K' => self.k(),
+				'S' => self.s(),
+				'B' => self.b(),
+				'C' => self.c(),
+				'W' => self.w(),
+				'X' => self.x(),
+				_ => panic!("`{}` in combinators", c),
+			};
+			let top = stack.last_mut().unwrap();
+			*top = Some(match *top {
+				Some(f) => self.ap(f, x),
+				None => x,
+			});
+		}
+		stack.pop().unwrap().unwrap()
+	}
+
+	// checks that each primitive translated into `base` behaves like the original, applying both
+	// to fresh variables and comparing their normal forms, themselves translated. Returns how many held
+	pub fn check_translation(&mut self, base: Base, budget: &Budget) -> Result<usize, String> {
+		let mut checked = 0;
+		let mut primitives = vec![Term::I, Term::K, Term::S, Term::B, Term::C, Term::W, Term::X];
+		for kind in [Bulk::B, Bulk::C, Bulk::S].iter() {
+			primitives.push(Term::Bulk(*kind, 2));
+			primitives.push(Term::Bulk(*kind, 3));
+		}
+		for t in primitives {
+			let arity = match t {
+				Term::I | Term::X => 1,
+				Term::K | Term::W => 2,
+				Term::Bulk(_, n) => n as usize + 2,
+				_ => 3,
+			};
+			let original = self.find_and_ref_up(t);
+			let translated = self.translate(original, base);
+			let vars = self.fresh_vars(original, arity);
+			let mut sides = vec![];
+			for &f in [original, translated].iter() {
+				let applied = vars.iter().fold(f, |f, &x| self.ap(f, x));
+				let r = self.reduce(applied, TraversalOrder::LeftmostOutermost, budget, |_, _| ());
+				if r.stop != Stop::NormalForm {
+					return Err(format!("{} applied to {} variables: {}", self.term_string(f), arity, r.stop));
+				}
+				sides.push(self.translate(r.key, base));
+			}
+			if sides[0] != sides[1] {
+				return Err(format!(
+					"{} translates to {}, which gives {} but not {}",
+					self.term_string(original),
+					self.term_string(translated),
+					self.term_string(sides[1]),
+					self.term_string(sides[0]),
+				));
+			}
+			checked += 1;
+		}
+		Ok(checked)
+	}
+}
@@ -0,0 +1,91 @@
+use std::fmt;
+
+use term::{Term, TermBase};
+
+// Kiselyov's bulk combinators, taking n arguments after the first two:
+// Bn f g x1..xn = f (g x1..xn), Cn f g x1..xn = f x1..xn g, Sn f g x1..xn = f x1..xn (g x1..xn)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Bulk {
+	B,
+	C,
+	S,
+}
+impl Bulk {
+	// `B2` and the like. The arity is at least 2, B1, C1 and S1 being B, C and S
+	pub fn parse(name: &str) -> Option<(Bulk, u32)> {
+		let kind = match name.get(..1)? {
+			"B" => Bulk::B,
+			"C" => Bulk::C,
+			"S" => Bulk::S,
+			_ => return None,
+		};
+		let digits = &name[1..];
+		if digits.starts_with('0') {
+			return None;
+		}
+		match digits.parse() {
+			Ok(n) if n >= 2 => Some((kind, n)),
+			_ => None,
+		}
+	}
+}
+impl fmt::Display for Bulk {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl TermBase {
+	// the combinator taking `n` arguments after the first two
+	pub(crate) fn bulk(&mut self, kind: Bulk, n: u32) -> usize {
+		match (kind, n) {
+			(Bulk::B, 1) => self.b(),
+			(Bulk::C, 1) => self.c(),
+			(Bulk::S, 1) => self.s(),
+			_ => self.find_and_ref_up(Term::Bulk(kind, n)),
+		}
+	}
+
+	// the combinator and arguments of `key` if it is a saturated bulk combinator
+	pub(crate) fn bulk_redex(&self, key: usize) -> Option<(Bulk, Vec<usize>)> {
+		let mut args = vec![];
+		let mut head = key;
+		while let Term::Ap(l, r) = self.term(head) {
+			args.push(r);
+			head = l;
+		}
+		match self.term(head) {
+			Term::Bulk(kind, n) if args.len() == n as usize + 2 => {
+				args.reverse();
+				Some((kind, args))
+			},
+			_ => None,
+		}
+	}
+
+	pub(crate) fn bulk_rewrite(&mut self, kind: Bulk, args: &[usize]) -> usize {
+		let (f, g, xs) = (args[0], args[1], &args[2..]);
+		match kind {
+			Bulk::B => {
+				let gx = self.ap_all(g, xs);
+				self.ap(f, gx)
+			},
+			Bulk::C => {
+				let fx = self.ap_all(f, xs);
+				self.ap(fx, g)
+			},
+			Bulk::S => {
+				let fx = self.ap_all(f, xs);
+				let gx = self.ap_all(g, xs);
+				self.ap(fx, gx)
+			},
+		}
+	}
+
+	fn ap_all(&mut self, mut f: usize, xs: &[usize]) -> usize {
+		for &x in xs {
+			f = self.ap(f, x);
+		}
+		f
+	}
+}
@@ -0,0 +1,130 @@
+use reduce::{Budget, Stop};
+use symbol;
+use term::{Term, TermBase};
+use traverse::TraversalOrder;
+
+// A common encoding a term is recognized as
+#[derive(Clone, Debug, PartialEq)]
+pub enum Decoded {
+	Numeral(u64),   // [f x](f(..(f x))), Church
+	Boolean(bool),  // [x y]x or [x y]y
+	List(Vec<usize>), // [c n](c e1(..(c en n))), the right fold of its elements
+}
+impl Decoded {
+	pub fn write(&self, tb: &mut TermBase, budget: &Budget, out: &mut String) {
+		match self {
+			Decoded::Numeral(n) => out.push_str(&format!("{} (Church)", n)),
+			Decoded::Boolean(b) => out.push_str(&b.to_string()),
+			Decoded::List(elements) => {
+				out.push('[');
+				for (i, &e) in elements.iter().enumerate() {
+					if i > 0 {
+						out.push_str(", ");
+					}
+					// elements show their first decoding, if any, without annotation
+					match tb.decode(e, budget).first() {
+						Some(Decoded::Numeral(n)) => out.push_str(&n.to_string()),
+						Some(d) => d.write(tb, budget, out),
+						None => tb.write_term(e, out),
+					}
+				}
+				out.push(']');
+			},
+		}
+	}
+}
+
+impl TermBase {
+	// every encoding `key` is recognized as, found by applying it to fresh variables and
+	// checking the shape of the normal form. `[f x]x` is at once 0, false and the empty list
+	pub fn decode(&mut self, key: usize, budget: &Budget) -> Vec<Decoded> {
+		let mut decoded = vec![];
+		let vars = self.fresh_vars(key, 2);
+		let (a, b) = (vars[0], vars[1]);
+		let nf = match self.applied_normal_form(key, a, b, budget) {
+			Some(nf) => nf,
+			None => return decoded,
+		};
+		if let Some(n) = self.numeral(nf, a, b) {
+			decoded.push(Decoded::Numeral(n));
+		}
+		if nf == a || nf == b {
+			decoded.push(Decoded::Boolean(nf == a));
+		}
+		if let Some(elements) = self.list(nf, a, b) {
+			decoded.push(Decoded::List(elements));
+		}
+		decoded
+	}
+
+	// like `= 7 (Church)`, or None if nothing was recognized
+	pub fn annotation(&mut self, key: usize, budget: &Budget) -> Option<String> {
+		let decoded = self.decode(key, budget);
+		if decoded.is_empty() {
+			return None;
+		}
+		let mut out = String::new();
+		for d in decoded {
+			if !out.is_empty() {
+				out.push(' ');
+			}
+			out.push_str("= ");
+			d.write(self, budget, &mut out);
+		}
+		Some(out)
+	}
+
+	// `n` variables free in neither `key` nor the definitions
+	pub(crate) fn fresh_vars(&mut self, key: usize, n: usize) -> Vec<usize> {
+		let taken = self.vars(key);
+		let names: Vec<String> = symbol::fresh_names()
+		.filter(|name| {
+			self.definition(name).is_none()
+			&& self.symbols.get(name).is_none_or(|sym| !taken.contains(&sym))
+		})
+		.take(n)
+		.collect();
+		names.iter().map(|name| {
+			let v = self.symbols.intern(name);
+			self.find_and_ref_up(Term::Var(v))
+		}).collect()
+	}
+
+	fn applied_normal_form(&mut self, key: usize, a: usize, b: usize, budget: &Budget) -> Option<usize> {
+		let applied = self.ap(key, a);
+		let applied = self.ap(applied, b);
+		let r = self.reduce(applied, TraversalOrder::LeftmostOutermost, budget, |_, _| ());
+		if r.stop == Stop::NormalForm { Some(r.key) } else { None }
+	}
+
+	// n for `f(..(f x))` with n applications of f
+	fn numeral(&self, mut key: usize, f: usize, x: usize) -> Option<u64> {
+		let mut n = 0;
+		while key != x {
+			match self.term(key) {
+				Term::Ap(l, r) if l == f => key = r,
+				_ => return None,
+			}
+			n += 1;
+		}
+		Some(n)
+	}
+
+	// the elements of `c e1(..(c en n))`, none of which may mention c or n
+	fn list(&self, mut key: usize, c: usize, n: usize) -> Option<Vec<usize>> {
+		let fresh = [self.term(c), self.term(n)];
+		let mut elements = vec![];
+		while key != n {
+			let (ce, rest) = match self.term(key) {
+				Term::Ap(l, r) => (l, r),
+				_ => return None,
+			};
+			match self.term(ce) {
+				Term::Ap(l, e) if l == c && !self.vars(e).iter().any(|&v| fresh.contains(&Term::Var(v))) => elements.push(e),
+				_ => return None,
+			}
+			key = rest;
+		}
+		Some(elements)
+	}
+}
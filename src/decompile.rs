@@ -0,0 +1,171 @@
+use fnv::{FnvHashMap, FnvHashSet};
+
+use reduce::{Budget, Limiter, Stop};
+use symbol::{self, Sym};
+use term::{Term, TermBase};
+use traverse::TraversalOrder;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Decompiled {
+	pub key: usize,   // a lambda term of Abs and Var nodes
+	pub arity: usize, // the abstractions in front of its head
+	pub steps: usize,
+}
+
+struct Readback<'a> {
+	limiter: Limiter<'a>,
+	steps: usize,
+	taken: FnvHashSet<Sym>, // the variables of the decompiled term
+	bound: Vec<Sym>,        // the fresh variables applied so far, innermost last
+	path: FnvHashMap<usize, usize>, // the terms being read back, with the step each was reached at
+}
+
+// how deep reading back goes when the budget does not say, rather than overflow the stack
+const READ_BACK_DEPTH: usize = 1000;
+
+impl TermBase {
+	// applies `key` to fresh variables until its head is a variable, then reads back
+	// the arguments the same way. Fails with the reason the budget ran out, or with a
+	// cycle if head reduction provably never ends or a term turns up inside its own
+	// read back, as Y x reads back to x (Y x), in which case there is no finite lambda form
+	pub fn decompile(&mut self, key: usize, budget: &Budget) -> Result<Decompiled, Stop> {
+		let bounded = Budget { max_depth: budget.max_depth.or(Some(READ_BACK_DEPTH)), ..budget.clone() };
+		// an abstraction at the head may not rewrite before its body has
+		let key = self.compile(key);
+		let mut rb = Readback {
+			limiter: Limiter::new(&bounded),
+			steps: 0,
+			taken: self.vars(key),
+			bound: vec![],
+			path: FnvHashMap::default(),
+		};
+		let key = self.read_back(key, 0, &mut rb)?;
+		let mut arity = 0;
+		let mut body = key;
+		while let Term::Abs(_, b) = self.term(body) {
+			arity += 1;
+			body = b;
+		}
+		Ok(Decompiled { key, arity, steps: rb.steps })
+	}
+
+	fn read_back(&mut self, key: usize, depth: usize, rb: &mut Readback) -> Result<usize, Stop> {
+		if rb.limiter.too_deep(depth) {
+			return Err(Stop::DepthLimit);
+		}
+		if rb.limiter.detect_cycles() {
+			if let Some(&start) = rb.path.get(&key) {
+				return Err(Stop::Cycle { start, length: rb.steps - start });
+			}
+		}
+		rb.path.insert(key, rb.steps);
+		let read = self.read_back_path(key, depth, rb);
+		rb.path.remove(&key);
+		read
+	}
+
+	fn read_back_path(&mut self, key: usize, depth: usize, rb: &mut Readback) -> Result<usize, Stop> {
+		let key = self.head_normalize(key, rb)?;
+		let mut args = vec![];
+		let mut head = key;
+		while let Term::Ap(l, r) = self.term(head) {
+			args.push(r);
+			head = l;
+		}
+		match self.term(head) {
+			Term::Var(_) |
+			Term::Idx(_) => {
+				let mut out = head;
+				for &arg in args.iter().rev() {
+					let arg = self.read_back(arg, depth + 1, rb)?;
+					out = self.ap(out, arg);
+				}
+				Ok(out)
+			},
+			// a combinator or lambda still waiting for arguments
+			_ => {
+				let v = self.fresh_var(rb);
+				let x = self.find_and_ref_up(Term::Var(v));
+				let applied = self.ap(key, x);
+				rb.bound.push(v);
+				let body = self.read_back(applied, depth + 1, rb);
+				rb.bound.pop();
+				Ok(self.find_and_ref_up(Term::Abs(v, body?)))
+			},
+		}
+	}
+
+	fn fresh_var(&mut self, rb: &Readback) -> Sym {
+		let name = symbol::fresh_names()
+		.find(|name| {
+			self.definition(name).is_none()
+			&& self.symbols.get(name).is_none_or(|sym| !rb.taken.contains(&sym) && !rb.bound.contains(&sym))
+		})
+		.unwrap();
+		self.symbols.intern(&name)
+	}
+
+	// parallel outermost steps until no redex is left along the spine. The head redex is always
+	// among those contracted, and contracting the others too brings terms like SII(SII) back
+	// to themselves, where leftmost-outermost steps would only grow them
+	fn head_normalize(&mut self, mut key: usize, rb: &mut Readback) -> Result<usize, Stop> {
+		let mut visited: FnvHashMap<usize, usize> = FnvHashMap::default();
+		while self.head_redex(key) {
+			if rb.limiter.detect_cycles() {
+				if let Some(&start) = visited.get(&key) {
+					return Err(Stop::Cycle { start, length: rb.steps - start });
+				}
+				visited.insert(key, rb.steps);
+			}
+			if let Some(stop) = rb.limiter.exceeded(rb.steps) {
+				return Err(stop);
+			}
+			key = self.step(key, TraversalOrder::ParallelOutermost);
+			rb.steps += 1;
+		}
+		Ok(key)
+	}
+
+	fn head_redex(&mut self, mut key: usize) -> bool {
+		loop {
+			if self.root_can_rewrite(key) {
+				return true;
+			}
+			match self.term(key) {
+				Term::Ap(l, _) => key = l,
+				// unfolded definitions may bring abstractions with them
+				Term::Abs(_, _) => return true,
+				_ => return false,
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn y_has_no_finite_lambda_form() {
+		let mut tb = TermBase::new();
+		let y = tb.parse("S(K(SII))(S(S(KS)K)(K(SII)))").unwrap();
+		match tb.decompile(y, &Budget::unlimited()) {
+			Err(Stop::Cycle { .. }) => (),
+			r => panic!("{:?}", r),
+		}
+		let no_cycles = Budget { detect_cycles: false, ..Budget::unlimited() };
+		assert_eq!(tb.decompile(y, &no_cycles), Err(Stop::DepthLimit));
+	}
+
+	#[test]
+	fn omega_has_no_lambda_form() {
+		let mut tb = TermBase::new();
+		for src in ["SII(SII)", "[x](x x)([x](x x))"].iter() {
+			let k = tb.parse(src).unwrap();
+			match tb.decompile(k, &Budget::steps(1000)) {
+				Err(Stop::Cycle { .. }) => (),
+				r => panic!("{}: {:?}", src, r),
+			}
+		}
+	}
+}
@@ -0,0 +1,201 @@
+use reduce::{Budget, Limiter, Stop};
+use term::{Term, TermBase};
+use traverse::TraversalOrder;
+
+// The verdict on two terms, by their normal forms
+#[derive(Clone, Debug, PartialEq)]
+pub enum Equality {
+	Equal(usize), // the shared normal form
+	Different {
+		left: usize, // the normal forms
+		right: usize,
+		first: (usize, usize), // their leftmost outermost subterms to differ
+	},
+	Unknown(Stop), // a side reached no normal form within the budget
+}
+
+// The verdict on two terms as functions, applying both to the same fresh variables
+#[derive(Clone, Debug, PartialEq)]
+pub enum Extensional {
+	Equal { arity: usize }, // the fresh variables after which the two sides agree
+	Different {
+		arity: usize,
+		first: (usize, usize), // the outermost subterms that differ, with variable heads
+	},
+	Unknown(Stop), // out of budget, depth bounding the variables applied and the nesting of arguments
+}
+
+struct Extension<'a> {
+	order: TraversalOrder,
+	budget: &'a Budget,
+	limiter: Limiter<'a>,
+	steps: usize, // over all normalizations
+	held: Vec<usize>, // the pairs of sides being compared, outermost first
+}
+
+impl TermBase {
+	// normalizes both sides, each under `budget`. Hash-consing makes equal normal forms one key.
+	// Whichever side is not being reduced survives collection under a node limit
+	pub fn equality(&mut self, left: usize, right: usize, order: TraversalOrder, budget: &Budget) -> Equality {
+		let r = self.reduce_keeping(left, order, budget, &[right], |_, _| ());
+		if r.stop != Stop::NormalForm {
+			return Equality::Unknown(r.stop);
+		}
+		let left = r.key;
+		let r = self.reduce_keeping(right, order, budget, &[left], |_, _| ());
+		if r.stop != Stop::NormalForm {
+			return Equality::Unknown(r.stop);
+		}
+		let right = r.key;
+		if left == right {
+			return Equality::Equal(left);
+		}
+		Equality::Different { left, right, first: self.first_difference(left, right) }
+	}
+
+	// the outermost subterms where `a` and `b` part ways, the leftmost if several
+	fn first_difference(&self, a: usize, b: usize) -> (usize, usize) {
+		match (self.term(a), self.term(b)) {
+			(Term::Ap(al, ar), Term::Ap(bl, br)) => {
+				if al != bl { self.first_difference(al, bl) } else { self.first_difference(ar, br) }
+			},
+			(Term::Abs(v, x), Term::Abs(w, y)) if v == w => self.first_difference(x, y),
+			(Term::Lam(x), Term::Lam(y)) => self.first_difference(x, y),
+			_ => (a, b),
+		}
+	}
+
+	// applies both sides to fresh variables until their normal forms are equal, or have variable
+	// heads. Heads that agree leave their arguments to compare the same way
+	pub fn extensional_equality(&mut self, left: usize, right: usize, order: TraversalOrder, budget: &Budget) -> Extensional {
+		let mut ext = Extension {
+			order,
+			budget,
+			limiter: Limiter::new(budget),
+			steps: 0,
+			held: vec![],
+		};
+		match self.extension(left, right, 0, &mut ext) {
+			Ok(verdict) => verdict,
+			Err(stop) => Extensional::Unknown(stop),
+		}
+	}
+
+	// the two sides are held for as long as they are compared, which keeps their arguments and
+	// the fresh variables applied to them alive too
+	fn extension(&mut self, left: usize, right: usize, depth: usize, ext: &mut Extension) -> Result<Extensional, Stop> {
+		let at = ext.held.len();
+		ext.held.push(left);
+		ext.held.push(right);
+		let verdict = self.extension_held(at, depth, ext);
+		ext.held.truncate(at);
+		verdict
+	}
+
+	fn extension_held(&mut self, at: usize, depth: usize, ext: &mut Extension) -> Result<Extensional, Stop> {
+		let mut arity = 0;
+		loop {
+			if ext.limiter.too_deep(depth + arity) {
+				return Err(Stop::DepthLimit);
+			}
+			ext.held[at] = self.extension_normal_form(ext.held[at], ext)?;
+			ext.held[at + 1] = self.extension_normal_form(ext.held[at + 1], ext)?;
+			let (left, right) = (ext.held[at], ext.held[at + 1]);
+			if left == right {
+				return Ok(Extensional::Equal { arity });
+			}
+			let (lhead, largs) = self.spine(left);
+			let (rhead, rargs) = self.spine(right);
+			if let (Term::Var(_), Term::Var(_)) = (self.term(lhead), self.term(rhead)) {
+				if lhead != rhead || largs.len() != rargs.len() {
+					return Ok(Extensional::Different { arity, first: self.first_difference(left, right) });
+				}
+				for (&l, &r) in largs.iter().zip(rargs.iter()) {
+					if let Extensional::Different { first, .. } = self.extension(l, r, depth + arity + 1, ext)? {
+						return Ok(Extensional::Different { arity, first });
+					}
+				}
+				return Ok(Extensional::Equal { arity });
+			}
+			let both = self.ap(left, right);
+			let x = self.fresh_vars(both, 1)[0];
+			ext.held[at] = self.ap(left, x);
+			ext.held[at + 1] = self.ap(right, x);
+			arity += 1;
+		}
+	}
+
+	// normalizes `key` with every held term surviving collection
+	fn extension_normal_form(&mut self, key: usize, ext: &mut Extension) -> Result<usize, Stop> {
+		let r = self.reduce_keeping(key, ext.order, ext.budget, &ext.held, |_, _| ());
+		ext.steps += r.steps;
+		if r.stop != Stop::NormalForm {
+			return Err(r.stop);
+		}
+		match ext.limiter.exceeded(ext.steps) {
+			Some(stop) => Err(stop),
+			None => Ok(r.key),
+		}
+	}
+
+	// the head of `key` and its arguments, first to last
+	fn spine(&self, mut key: usize) -> (usize, Vec<usize>) {
+		let mut args = vec![];
+		while let Term::Ap(l, r) = self.term(key) {
+			args.push(r);
+			key = l;
+		}
+		args.reverse();
+		(key, args)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn budget(nodes: usize) -> Budget {
+		Budget {
+			max_steps: Some(100_000),
+			max_nodes: Some(nodes),
+			..Budget::default()
+		}
+	}
+
+	#[test]
+	fn equality_under_node_limit() {
+		let mut tb = TermBase::new();
+		let a = tb.parse("S(SKK)(SKK)(S(SKK)(SKK)(S(SKK)(SKK)x))").unwrap();
+		let b = tb.parse("S I I (S I I (S I I x))").unwrap();
+		let verdict = tb.equality(a, b, TraversalOrder::LeftmostOutermost, &budget(60));
+		assert!(tb.collections > 0);
+		assert_eq!(tb.check_invariants(), Ok(()));
+		let nf = tb.parse("x x (x x) (x x (x x))").unwrap();
+		assert_eq!(verdict, Equality::Equal(nf));
+	}
+
+	#[test]
+	fn extensional_equality_under_node_limit() {
+		let mut tb = TermBase::new();
+		let a = tb.parse("S(SKK)(SKK)(S(SKK)(SKK)(S(SKK)(SKK)x))").unwrap();
+		let b = tb.parse("S I I (S I I (S I I x))").unwrap();
+		let verdict = tb.extensional_equality(a, b, TraversalOrder::LeftmostOutermost, &budget(60));
+		assert!(tb.collections > 0);
+		assert_eq!(tb.check_invariants(), Ok(()));
+		assert_eq!(verdict, Extensional::Equal { arity: 0 });
+	}
+
+	#[test]
+	fn extensional_equality_applies_fresh_variables() {
+		let mut tb = TermBase::new();
+		let order = TraversalOrder::LeftmostOutermost;
+		let skk = tb.parse("SKK").unwrap();
+		let sks = tb.parse("SKS").unwrap();
+		assert_eq!(tb.extensional_equality(skk, sks, order, &Budget::steps(100)), Extensional::Equal { arity: 1 });
+		let k = tb.parse("K").unwrap();
+		let ki = tb.parse("KI").unwrap();
+		let x = tb.parse("x").unwrap();
+		let y = tb.parse("y").unwrap();
+		assert_eq!(tb.extensional_equality(k, ki, order, &Budget::steps(100)), Extensional::Different { arity: 2, first: (x, y) });
+	}
+}
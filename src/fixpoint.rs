@@ -0,0 +1,109 @@
+use std::fmt;
+use std::str::FromStr;
+
+use parser::{ParseError, Parser};
+use symbol::{self, Sym};
+use term::{Term, TermBase};
+
+// The fixpoint combinator `rec` definitions are compiled through
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Fixpoint {
+	Y,     // Curry's, Yf = f(Yf) up to conversion
+	Theta, // Turing's, Θf reduces to f(Θf)
+}
+impl Fixpoint {
+	pub const ALL: [Fixpoint; 2] = [Fixpoint::Y, Fixpoint::Theta];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Fixpoint::Y => "y",
+			Fixpoint::Theta => "theta",
+		}
+	}
+
+	pub fn combinator(self) -> &'static str {
+		match self {
+			Fixpoint::Y => "S(K(SII))(S(S(KS)K)(K(SII)))",
+			// AA with A = [x y](y(x x y))
+			Fixpoint::Theta => "S(K(SI))(SII)(S(K(SI))(SII))",
+		}
+	}
+}
+impl fmt::Display for Fixpoint {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
+impl FromStr for Fixpoint {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Fixpoint::ALL.iter()
+		.cloned()
+		.find(|x| x.name().eq_ignore_ascii_case(s))
+		.ok_or_else(|| format!("unknown fixpoint `{}`, expected y or theta", s))
+	}
+}
+
+impl TermBase {
+	// parses and defines `f = ..; g = ..`, whose terms may refer to any name of the group.
+	// A single `f = e` becomes `fix([f]e)`. A group becomes a tuple of its members,
+	// `fix([t s](s e1' .. en'))` where each `ei' = ([f1 .. fn]ei)(t sel1) .. (t seln)`,
+	// and each `fi` that tuple applied to the selector `seli = [x1 .. xn]xi`
+	pub fn parse_rec(&mut self, src: &str, fixpoint: Fixpoint) -> Result<Vec<(Sym, usize)>, ParseError> {
+		let group = Parser::parse_group(self, src)?;
+		let fix = self.combinators(fixpoint.combinator());
+		let base = self.base();
+		let fix = self.translate(fix, base);
+		let defs = if let [(name, body)] = group[..] {
+			let f = self.find_and_ref_up(Term::Abs(name, body));
+			vec![(name, self.ap(fix, f))]
+		} else {
+			let names: Vec<Sym> = group.iter().map(|&(name, _)| name).collect();
+			let mut taken = names.clone();
+			for &(_, body) in group.iter() {
+				taken.extend(self.vars(body));
+			}
+			let t = self.fresh_sym(&mut taken);
+			let s = self.fresh_sym(&mut taken);
+			let selectors: Vec<usize> = (0..names.len()).map(|i| {
+				let xs: Vec<Sym> = (0..names.len()).map(|_| self.fresh_sym(&mut taken)).collect();
+				let x = self.find_and_ref_up(Term::Var(xs[i]));
+				self.abs_all(&xs, x)
+			}).collect();
+			let t_var = self.find_and_ref_up(Term::Var(t));
+			let members: Vec<usize> = selectors.iter().map(|&sel| self.ap(t_var, sel)).collect();
+			let mut tuple = self.find_and_ref_up(Term::Var(s));
+			for &(_, body) in group.iter() {
+				let mut e = self.abs_all(&names, body);
+				for &m in members.iter() {
+					e = self.ap(e, m);
+				}
+				tuple = self.ap(tuple, e);
+			}
+			let tuple = self.abs_all(&[t, s], tuple);
+			let fixed = self.ap(fix, tuple);
+			names.iter().zip(selectors).map(|(&name, sel)| (name, self.ap(fixed, sel))).collect()
+		};
+		for &(name, key) in defs.iter() {
+			self.define(self.name(name).to_owned().as_str(), key).expect("parsed names are definable");
+		}
+		Ok(defs)
+	}
+
+	fn abs_all(&mut self, vs: &[Sym], mut body: usize) -> usize {
+		for &v in vs.iter().rev() {
+			body = self.find_and_ref_up(Term::Abs(v, body));
+		}
+		body
+	}
+
+	// a variable unlike any in `taken`, which it joins
+	fn fresh_sym(&mut self, taken: &mut Vec<Sym>) -> Sym {
+		let name = symbol::fresh_names()
+		.find(|name| self.definition(name).is_none() && self.symbols.get(name).is_none_or(|sym| !taken.contains(&sym)))
+		.unwrap();
+		let sym = self.symbols.intern(&name);
+		taken.push(sym);
+		sym
+	}
+}
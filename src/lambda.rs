@@ -0,0 +1,122 @@
+use fnv::FnvHashMap;
+
+use symbol::Sym;
+use term::{Term, TermBase};
+
+// Lambda terms are stored nameless, as `Lam` and `Idx` nodes with de Bruijn indices,
+// so alpha-equivalent terms share a key and beta reduction cannot capture variables.
+impl TermBase {
+	// replaces every abstraction in `key` by its de Bruijn form
+	pub fn lambda(&mut self, key: usize) -> usize {
+		let mut env = vec![];
+		let mut memo = FnvHashMap::default();
+		self.lambda_rec(key, &mut env, &mut memo)
+	}
+
+	fn lambda_rec(&mut self, key: usize, env: &mut Vec<Sym>, memo: &mut FnvHashMap<(usize, Vec<Sym>), usize>) -> usize {
+		if let Some(&k) = memo.get(&(key, env.clone())) {
+			return k;
+		}
+		let k = match self.term(key) {
+			Term::Var(x) => match env.iter().rev().position(|&y| y == x) {
+				Some(index) => self.find_and_ref_up(Term::Idx(index as u32)),
+				None => key,
+			},
+			Term::Abs(v, body) => {
+				env.push(v);
+				let body = self.lambda_rec(body, env, memo);
+				env.pop();
+				self.find_and_ref_up(Term::Lam(body))
+			},
+			Term::Ap(l, r) => {
+				let l = self.lambda_rec(l, env, memo);
+				let r = self.lambda_rec(r, env, memo);
+				self.ap(l, r)
+			},
+			_ => key,
+		};
+		memo.insert((key, env.clone()), k);
+		k
+	}
+
+	// the body of `Lam(body)` with `arg` put in place of its variable
+	pub(crate) fn beta(&mut self, body: usize, arg: usize) -> usize {
+		let mut memo = FnvHashMap::default();
+		self.substitute(body, 0, arg, &mut memo)
+	}
+
+	// replaces index `depth` by `arg`, lifted over the `depth` binders in between,
+	// and lowers the indices bound outside `body`'s lambda
+	fn substitute(&mut self, key: usize, depth: u32, arg: usize, memo: &mut FnvHashMap<(usize, u32), usize>) -> usize {
+		if let Some(&k) = memo.get(&(key, depth)) {
+			return k;
+		}
+		let k = match self.term(key) {
+			Term::Idx(i) if i == depth => self.shift(arg, depth, 0),
+			Term::Idx(i) if i > depth => self.find_and_ref_up(Term::Idx(i - 1)),
+			Term::Lam(body) => {
+				let body = self.substitute(body, depth + 1, arg, memo);
+				self.find_and_ref_up(Term::Lam(body))
+			},
+			Term::Ap(l, r) => {
+				let l = self.substitute(l, depth, arg, memo);
+				let r = self.substitute(r, depth, arg, memo);
+				self.ap(l, r)
+			},
+			_ => key,
+		};
+		memo.insert((key, depth), k);
+		k
+	}
+
+	// adds `by` to the indices of `key` bound outside it, being those from `cutoff` up
+	fn shift(&mut self, key: usize, by: u32, cutoff: u32) -> usize {
+		if by == 0 {
+			return key;
+		}
+		let mut memo = FnvHashMap::default();
+		self.shift_rec(key, by, cutoff, &mut memo)
+	}
+
+	fn shift_rec(&mut self, key: usize, by: u32, cutoff: u32, memo: &mut FnvHashMap<(usize, u32), usize>) -> usize {
+		if let Some(&k) = memo.get(&(key, cutoff)) {
+			return k;
+		}
+		let k = match self.term(key) {
+			Term::Idx(i) if i >= cutoff => self.find_and_ref_up(Term::Idx(i + by)),
+			Term::Lam(body) => {
+				let body = self.shift_rec(body, by, cutoff + 1, memo);
+				self.find_and_ref_up(Term::Lam(body))
+			},
+			Term::Ap(l, r) => {
+				let l = self.shift_rec(l, by, cutoff, memo);
+				let r = self.shift_rec(r, by, cutoff, memo);
+				self.ap(l, r)
+			},
+			_ => key,
+		};
+		memo.insert((key, cutoff), k);
+		k
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use reduce::{Budget, Stop};
+	use traverse::TraversalOrder;
+
+	#[test]
+	fn beta_shifts_arguments_under_binders() {
+		let mut tb = TermBase::new();
+		// λz.(λx.λy.x) z: z moves under the binder of y, so its index goes from 0 to 1
+		let k = tb.parse("[z]([x y]x) z").unwrap();
+		let k = tb.lambda(k);
+		let r = tb.normalize(k, TraversalOrder::LeftmostOutermost, &Budget::steps(100));
+		assert_eq!(r.stop, Stop::NormalForm);
+		let expected = tb.parse("[z y]z").unwrap();
+		let expected = tb.lambda(expected);
+		assert_eq!(tb.term_string(r.key), "[x y]x");
+		assert_eq!(r.key, expected);
+	}
+}
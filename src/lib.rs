@@ -0,0 +1,38 @@
+extern crate fnv;
+extern crate slab;
+
+mod abstraction;
+mod base;
+mod bulk;
+mod decode;
+mod decompile;
+mod equality;
+mod fixpoint;
+mod term;
+mod traverse;
+mod types;
+mod lambda;
+mod parser;
+mod prelude;
+mod print;
+mod symbol;
+mod refcount;
+mod rule;
+mod reduce;
+
+pub use abstraction::Abstraction;
+pub use base::Base;
+pub use bulk::Bulk;
+pub use decode::Decoded;
+pub use decompile::Decompiled;
+pub use equality::{Equality, Extensional};
+pub use fixpoint::Fixpoint;
+pub use term::{Term, TermBase, Unfold};
+pub use parser::ParseError;
+pub use prelude::PRELUDE;
+pub use print::Folds;
+pub use refcount::RcTermBase;
+pub use symbol::{is_ident, Sym, SymbolTable};
+pub use reduce::{Budget, Reduction, Stop};
+pub use traverse::{TraversalOrder, Traverser};
+pub use types::Type;
@@ -1,491 +1,394 @@
-#![allow(dead_code, unused_variables)]
+extern crate combinatory;
+use combinatory::{is_ident, Budget, Equality, Extensional, Fixpoint, Folds, RcTermBase, Reduction, Stop, Term, TermBase, TraversalOrder, Unfold};
 
-#[macro_use]
-extern crate nom;
-
-extern crate fnv;
-use fnv::{FnvHashMap, FnvHashSet};
-
-extern crate slab;
-use slab::Slab;
-
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
-
-use std::{
-	fmt,
-	env,
-	io::{
-		self,
-		Write,
-	},
+use std::io::{
+	self,
+	BufRead,
 };
 
-#[derive(Copy, Clone, Debug, PartialEq)]
-enum RewriteKey {
-	NormalForm,
-	CanRewrite,
-	Unknown,
-}
+const GC_MIN_NODES: usize = 4096;
+const PRELUDE_STEPS: usize = 10_000;
+const DECODE_STEPS: usize = 1000;
+const TRANSLATION_STEPS: usize = 1000;
 
-#[derive(Debug)]
-struct TermEntry {
-	term: Term,
-	rewrite_key: RewriteKey,
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Store {
+	Gc, // rewrite in the TermBase, collecting between lines
+	Rc, // rewrite in an RcTermBase, freeing nodes as they die
 }
 
-
-#[derive(Clone, PartialEq, Hash, Copy, Debug)]
-enum Term {
-	I, K, S,
-	Var(char),
-	Ap(usize, usize),
-	Abs(char, usize),
+struct Repl {
+	tb: TermBase,
+	rc: RcTermBase,
+	store: Store,
+	order: TraversalOrder,
+	budget: Budget,
+	trace: bool,
+	fold: bool, // print subterms equal to a definition by its name
+	decode: bool, // annotate normal forms recognized as Church numerals, booleans or lists
+	fixpoint: Fixpoint,
 }
-impl Term {
-	pub fn atomic(&self) -> bool {
-		match self {
-			Term::I |
-			Term::K |
-			Term::S |
-			Term::Var(_) => true,
-			Term::Ap(_,_) |
-			Term::Abs(_,_) => false,
+impl Repl {
+	fn new() -> Self {
+		Repl {
+			tb: TermBase::new(),
+			rc: RcTermBase::new(),
+			store: Store::Gc,
+			order: TraversalOrder::LeftmostOutermost,
+			budget: Budget::steps(32),
+			trace: true,
+			fold: false,
+			decode: true,
+			fixpoint: Fixpoint::Y,
 		}
 	}
-}
-
-type TSlab = Slab<TermEntry>;
-
-#[derive(Debug)]
-struct TermBase {
-	slab: TSlab,
-	slab_locator: FnvHashMap<usize, usize>, //term digest to slab key
-	defined: FnvHashMap<char, usize>,
-	i_key: usize,
-	k_key: usize,
-	s_key: usize,
-}
-impl TermBase {
-	pub fn new() -> Self {
-		let mut t = TermBase {
-			slab: TSlab::with_capacity(128),
-			slab_locator: FnvHashMap::default(),
-			defined: FnvHashMap::default(),
-			i_key: 0,
-			k_key: 0,
-			s_key: 0,
-		};
-		t.i_key = t.find_and_ref_up(Term::I);
-		t.k_key = t.find_and_ref_up(Term::K);
-		t.s_key = t.find_and_ref_up(Term::S);
-		t
-	}
 
-	fn find_and_ref_up(&mut self, term: Term) -> usize {
-		let h = {
-			let mut hasher = DefaultHasher::new();
-			term.hash(&mut hasher);
-			hasher.finish() as usize
-		};
-		if let Some(&slab_key) = self.slab_locator.get(&h) {
-			slab_key
-		} else {
-			let slab_key = self.slab.insert(TermEntry {
-				term: term,
-				rewrite_key: RewriteKey::Unknown,
-			});
-			self.slab_locator.insert(h, slab_key);
-			slab_key
-		}
-	}
-
-	fn root_can_rewrite(&mut self, key: usize) -> bool {
-		let t = self.slab[key].term;
-		if let Term::Abs(v, a) = t {
-			let q = self.slab[a].term;
-			if q.atomic() {
-				return true
-			} else if let Term::Ap(al, ar) = q {
-				return true
+	fn line(&mut self, line1: &str) {
+		if is_definition(line1) {
+			match self.tb.parse_definition(line1) {
+				Ok((name, k)) => println!("defined <{},{}>", self.tb.name(name), k),
+				Err(e) => {
+					println!("{}", e);
+					println!("{}", e.caret(line1));
+				},
 			}
-		}
-		if let Term::Ap(l, r) = t {
-			if l == self.i_key {
-				return true
+		} else if let Some(rest) = line1.strip_prefix("rec ") {
+			match self.tb.parse_rec(rest, self.fixpoint) {
+				Ok(defs) => for (name, k) in defs {
+					println!("defined {} = {}", self.tb.name(name), self.tb.term_string(k));
+				},
+				Err(mut e) => {
+					e.pos += line1.len() - rest.len();
+					println!("{}", e);
+					println!("{}", e.caret(line1));
+				},
 			}
-			if let Term::Ap(ll, lr) = self.slab[l].term {
-				if ll == self.k_key {
-					return true
-				}
+		} else if let Some(rest) = line1.strip_prefix("rule ") {
+			match self.tb.parse_rule(rest) {
+				Ok(name) => {
+					let name = self.tb.name(name);
+					let (pattern, rhs) = self.tb.rules().find(|(pattern, _)| pattern.split(' ').next() == Some(name)).unwrap();
+					println!("rule {} = {}", pattern, self.tb.term_string(rhs));
+				},
+				Err(mut e) => {
+					e.pos += line1.len() - rest.len();
+					println!("{}", e);
+					println!("{}", e.caret(line1));
+				},
 			}
-			if let Term::Ap(ll, lr) = self.slab[l].term {
-				if let Term::Ap(lll, llr) = self.slab[ll].term {
-					if lll == self.s_key {
-						return true
-					}
-				}
+		} else if let Some(rest) = line1.strip_prefix(":ext") {
+			match rest.find("==") {
+				Some(at) => self.compare_extensionally(line1, line1.len() - rest.len() + at),
+				None => println!("expected `:ext a == b`"),
 			}
-		}
-		false
-	}
-
-	fn root_rewrite(&mut self, key: usize) -> usize {
-		let t = self.slab[key].term;
-		if let Term::Abs(v, a) = t {
-			let q = self.slab[a].term;
-			if q.atomic() {
-				if let Term::Var(x) = q {
-					if v == x { return self.i_key }
+		} else if let Some(at) = line1.find("==") {
+			self.compare(line1, at);
+		} else if let Some(rest) = line1.strip_prefix(">*") {
+			self.rewrite(line1, rest, false);
+		} else if let Some(rest) = line1.strip_prefix(">\\") {
+			self.rewrite(line1, rest, true);
+		} else if let Some(rest) = line1.strip_prefix(":compile") {
+			if let Some(k) = self.parse(line1, rest) {
+				// a lone name shows its definition
+				let k = match self.tb.term(k) {
+					Term::Const(name) => self.tb.definition(self.tb.name(name)).unwrap_or(k),
+					_ => k,
+				};
+				let c = self.tb.compile(k);
+				let folds = self.folds();
+				println!("   {}", self.tb.term_string_folded(c, &folds));
+				println!("size {} ({} before compiling)", self.tb.size(c), self.tb.size(k));
+			}
+		} else if let Some(rest) = line1.strip_prefix(":decompile") {
+			if let Some(k) = self.parse(line1, rest) {
+				self.tb.set_unfold(Unfold::Compiled);
+				match self.tb.decompile(k, &self.budget) {
+					Ok(d) => {
+						let folds = self.folds();
+						println!("   {}", self.tb.term_string_folded(d.key, &folds));
+						println!("arity {} after {} steps", d.arity, d.steps);
+					},
+					Err(stop @ Stop::Cycle { .. }) => println!("no finite lambda form: {}", stop),
+					Err(stop) => println!("no lambda form found: {}", stop),
 				}
-				let k = self.k_key;
-				return self.find_and_ref_up(Term::Ap(k, a));
-			} else if let Term::Ap(al, ar) = q {
-				let s = self.s_key;
-				let new_left = self.find_and_ref_up(Term::Abs(v, al));
-				let new_right = self.find_and_ref_up(Term::Abs(v, ar));
-				let inner = self.find_and_ref_up(Term::Ap(s, new_left));
-				let outer = self.find_and_ref_up(Term::Ap(inner, new_right));
-				return outer;
 			}
-		}
-		if let Term::Ap(l, r) = t {
-			if l == self.i_key {
-				return r;
+		} else if line1.trim() == ":prelude" {
+			match self.tb.load_prelude() {
+				Ok(names) => {
+					let names: Vec<&str> = names.into_iter().map(|name| self.tb.name(name)).collect();
+					println!("defined {}", names.join(" "));
+				},
+				Err(e) => println!("{}", e),
 			}
-			if let Term::Ap(ll, lr) = self.slab[l].term {
-				if ll == self.k_key {
-					return lr;
+		} else if let Some(rest) = line1.strip_prefix(":type") {
+			if let Some(k) = self.parse(line1, rest) {
+				match self.tb.principal_type(k) {
+					Ok(t) => println!("   {} : {}", self.tb.term_string(k), t),
+					Err(e) => println!("{}", e),
 				}
 			}
-			if let Term::Ap(ll, lr) = self.slab[l].term {
-				if let Term::Ap(lll, llr) = self.slab[ll].term {
-					if lll == self.s_key {
-						let left = self.find_and_ref_up(Term::Ap(llr, r));
-						let right = self.find_and_ref_up(Term::Ap(lr, r));
-						let whole = self.find_and_ref_up(Term::Ap(left, right));
-						return whole;
-					}
-				}
+		} else if let Some(rest) = line1.strip_prefix(":fix") {
+			match rest.trim().parse() {
+				Ok(f) => self.fixpoint = f,
+				Err(e) => println!("{}", e),
+			}
+			println!("fixpoint {}", self.fixpoint);
+		} else if let Some(rest) = line1.strip_prefix(":store") {
+			match rest.trim() {
+				"gc" => self.store = Store::Gc,
+				"rc" => self.store = Store::Rc,
+				_ => println!("expected `:store gc` or `:store rc`"),
+			}
+			println!("store {:?}", self.store);
+		} else if let Some(rest) = line1.strip_prefix(":strategy") {
+			match rest.trim().parse() {
+				Ok(o) => self.order = o,
+				Err(e) => println!("{}", e),
+			}
+			println!("strategy {}", self.order);
+		} else if let Some(rest) = line1.strip_prefix(":abstraction") {
+			if let Err(e) = rest.trim().parse().and_then(|a| self.tb.set_abstraction(a)) {
+				println!("{}", e);
+			}
+			println!("abstraction {}", self.tb.abstraction());
+		} else if let Some(rest) = line1.strip_prefix(":base") {
+			if let Err(e) = rest.trim().parse().and_then(|b| self.tb.set_base(b)) {
+				println!("{}", e);
+			}
+			let base = self.tb.base();
+			match self.tb.check_translation(base, &Budget::steps(TRANSLATION_STEPS)) {
+				Ok(checked) => println!("base {} ({} translations checked)", base, checked),
+				Err(e) => println!("base {}, but a translation failed: {}", base, e),
+			}
+		} else if let Some(rest) = line1.strip_prefix(":translate") {
+			// `:translate base term`
+			let rest = rest.trim_start();
+			let (name, src) = rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len()));
+			match name.parse() {
+				Ok(base) => if let Some(k) = self.parse(line1, src) {
+					let k = self.tb.compile(k);
+					let t = self.tb.translate(k, base);
+					println!("   {}", self.tb.term_string(t));
+					println!("size {} ({} in {})", self.tb.size(t), self.tb.size(k), self.tb.base());
+				},
+				Err(e) => println!("{}", e),
+			}
+		} else if let Some(rest) = line1.strip_prefix(":budget") {
+			if let Err(e) = self.budget.update(rest) {
+				println!("{}", e);
+			}
+			println!("budget {}", self.budget);
+		} else if let Some(rest) = line1.strip_prefix(":trace") {
+			match rest.trim() {
+				"on" => self.trace = true,
+				"off" => self.trace = false,
+				_ => println!("expected `:trace on` or `:trace off`"),
+			}
+			println!("trace {}", if self.trace { "on" } else { "off" });
+		} else if let Some(rest) = line1.strip_prefix(":fold") {
+			match rest.trim() {
+				"on" => self.fold = true,
+				"off" => self.fold = false,
+				_ => println!("expected `:fold on` or `:fold off`"),
 			}
+			println!("fold {}", if self.fold { "on" } else { "off" });
+		} else if let Some(rest) = line1.strip_prefix(":decode") {
+			match rest.trim() {
+				"on" => self.decode = true,
+				"off" => self.decode = false,
+				_ => println!("expected `:decode on` or `:decode off`"),
+			}
+			println!("decode {}", if self.decode { "on" } else { "off" });
+		} else {
+			println!("Failed to understand");
 		}
-		key //no change!
 	}
 
-	fn normal_form(&mut self, key: usize) -> bool {
-		self.assure_rewrite_key_known(key);
-		self.slab[key].rewrite_key == RewriteKey::NormalForm
+	fn folds(&mut self) -> Folds {
+		if self.fold { self.tb.folds() } else { Folds::default() }
 	}
 
-	fn assure_rewrite_key_known(&mut self, key: usize) {
-		if self.slab[key].rewrite_key == RewriteKey::Unknown {
-			if match self.slab[key].term {
-				Term::I |
-				Term::K |
-				Term::S |
-				Term::Var(_) => true,
-				Term::Ap(l, r) => {
-					self.normal_form(l)
-					&& self.normal_form(r)
-					&& !self.root_can_rewrite(key)
-				}
-				Term::Abs(v, term) => {
-					!self.root_can_rewrite(key)
-					&& self.normal_form(term)
-				}
-			} {
-				self.slab[key].rewrite_key = RewriteKey::NormalForm;
-			} else {
-				self.slab[key].rewrite_key = RewriteKey::CanRewrite;
-			}
-		}
+	// parses `src`, a suffix of `line1`, showing errors against the whole line
+	fn parse(&mut self, line1: &str, src: &str) -> Option<usize> {
+		self.parse_at(line1, src, line1.len() - src.len())
 	}
 
-
-	fn outermost_leftmost(&mut self, key: usize) -> usize {
-		self.assure_rewrite_key_known(key);
-		if self.normal_form(key) {
-			return key;
-		}
-		use RewriteKey::*;
-		let t = self.slab[key].term;
-		let new_key = self.root_rewrite(key);
-		if new_key != key {
-			return new_key;
-		}
-		match t {
-			Term::I |
-			Term::K |
-			Term::S |
-			Term::Var(_) => panic!("normal form didnt catch"),
-			Term::Ap(l, r) => {
-				let x = self.outermost_leftmost(l);
-				if x != l {
-					let z = self.find_and_ref_up(Term::Ap(x, r));
-					return z;
-				}
-				let x = self.outermost_leftmost(r);
-				if x != r {
-					let z = self.find_and_ref_up(Term::Ap(l, x));
-					return z;
-				}
-				panic!("WTF shortcut didnt help me");
+	// parses `src`, found at byte `offset` of `line1`
+	fn parse_at(&mut self, line1: &str, src: &str, offset: usize) -> Option<usize> {
+		match self.tb.parse(src) {
+			Ok(k) => Some(k),
+			Err(mut e) => {
+				e.pos += offset;
+				println!("{}", e);
+				println!("{}", e.caret(line1));
+				None
 			},
-			Term::Abs(v, term) => {
-				let x = self.outermost_leftmost(term);
-				if x != term {
-					let z = self.find_and_ref_up(Term::Abs(v, x));
-					return z;
-				}
-				panic!("WTF MANG");
-			}
 		}
 	}
 
-	fn print_maybe_parens(&self, key: usize) {
-		if self.slab[key].term.atomic() {
-			self.print_term(key);
+	// `>* term`, or `>*{steps=.. nodes=.. time=..} term` to override the budget once.
+	// `>\ term` beta reduces the abstractions instead of compiling them to combinators
+	fn rewrite(&mut self, line1: &str, src: &str, lambda: bool) {
+		let mut budget = self.budget.clone();
+		let src = if let Some(rest) = src.trim_start().strip_prefix('{') {
+			let close = match rest.find('}') {
+				Some(close) => close,
+				None => return println!("unclosed `{{` in budget"),
+			};
+			if let Err(e) = budget.update(&rest[..close]) {
+				return println!("{}", e);
+			}
+			&rest[close+1..]
 		} else {
-			print!("(");
-			self.print_term(key);
-			print!(")");
+			src
+		};
+		if let Some(k) = self.parse(line1, src) {
+			let k = if lambda {
+				self.tb.set_unfold(Unfold::Lambda);
+				self.tb.lambda(k)
+			} else {
+				self.tb.set_unfold(Unfold::Compiled);
+				self.tb.compile(k)
+			};
+			let folds = self.folds();
+			println!("REWRITING");
+			println!("   {}", self.tb.term_string_folded(k, &folds));
+			let trace = self.trace;
+			match self.store {
+				Store::Gc => {
+					let r = self.tb.reduce(k, self.order, &budget, |tb, k| if trace {
+						println!("-> {}", tb.term_string_folded(k, &folds));
+					});
+					self.report(&r, r.key, &folds);
+				},
+				Store::Rc if self.order != TraversalOrder::LeftmostOutermost => {
+					println!("the rc store only rewrites leftmost-outermost");
+				},
+				Store::Rc => {
+					let k = match self.rc.import(&mut self.tb, k) {
+						Ok(k) => k,
+						Err(e) => return println!("{}", e),
+					};
+					let tb = &self.tb;
+					let r = self.rc.reduce(k, &budget, |rc, k| if trace {
+						println!("-> {}", rc.term_string(k, tb));
+					});
+					let e = self.rc.export(r.key, &mut self.tb);
+					self.report(&r, e, &folds);
+					if budget.detect_cycles && r.stop != Stop::NormalForm {
+						println!("the rc store reuses freed keys, so cannot detect cycles");
+					}
+					self.rc.ref_down(r.key);
+					println!("rc peak {} nodes, {} live", self.rc.peak(), self.rc.len());
+				},
+			}
 		}
 	}
 
-	fn print_term(&self, key: usize) {
-		let t = self.slab[key].term;
-        match t {
-        	Term::Ap(l, r) => {
-        		self.print_term(l);
-        		self.print_maybe_parens(r);
-        	},
-        	Term::Abs(v, _) => {
-        		print!("[");
-        		let mut k = key;
-        		while let Term::Abs(v, term) = self.slab[k].term {
-        			print!("{}", v);
-        			k = term;
-        		}
-        		print!("]");
-        		self.print_maybe_parens(k);
-        	},
-        	Term::I => print!("I"),
-        	Term::K => print!("K"),
-        	Term::S => print!("S"),
-        	Term::Var(c) => print!("{}", c),
-        }
+	// `a == b`, comparing compiled normal forms, each side under the budget
+	fn compare(&mut self, line1: &str, at: usize) {
+		let left = match self.parse_at(line1, &line1[..at], 0) {
+			Some(k) => k,
+			None => return,
+		};
+		let right = match self.parse(line1, &line1[at+2..]) {
+			Some(k) => k,
+			None => return,
+		};
+		self.tb.set_unfold(Unfold::Compiled);
+		let left = self.tb.compile(left);
+		let right = self.tb.compile(right);
+		let folds = self.folds();
+		match self.tb.equality(left, right, self.order, &self.budget) {
+			Equality::Equal(nf) => println!("equal: both reach {}", self.tb.term_string_folded(nf, &folds)),
+			Equality::Different { left, right, first: (a, b) } => {
+				println!("different: {}", self.tb.term_string_folded(left, &folds));
+				println!("      but: {}", self.tb.term_string_folded(right, &folds));
+				println!("first difference {} vs {}", self.tb.term_string_folded(a, &folds), self.tb.term_string_folded(b, &folds));
+			},
+			Equality::Unknown(stop) => println!("unknown: {} ({})", stop, self.order),
+		}
 	}
 
-	fn gc_all_but_defined(&mut self) {
-		let x: Vec<_> = self.defined.values().map(|x| *x).collect();
-		self.gc_all_but(x.into_iter())
+	// `:ext a == b`, applying both sides to the same fresh variables until they agree
+	fn compare_extensionally(&mut self, line1: &str, at: usize) {
+		let start = ":ext".len();
+		let left = match self.parse_at(line1, &line1[start..at], start) {
+			Some(k) => k,
+			None => return,
+		};
+		let right = match self.parse(line1, &line1[at+2..]) {
+			Some(k) => k,
+			None => return,
+		};
+		self.tb.set_unfold(Unfold::Compiled);
+		let left = self.tb.compile(left);
+		let right = self.tb.compile(right);
+		let folds = self.folds();
+		match self.tb.extensional_equality(left, right, self.order, &self.budget) {
+			Extensional::Equal { arity } => println!("extensionally equal, agreeing after {}", fresh_variables(arity)),
+			Extensional::Different { arity, first: (a, b) } => {
+				println!("extensionally different after {}", fresh_variables(arity));
+				println!("first difference {} vs {}", self.tb.term_string_folded(a, &folds), self.tb.term_string_folded(b, &folds));
+			},
+			Extensional::Unknown(stop) => println!("unknown: {} ({})", stop, self.order),
+		}
 	}
 
-	fn gc_all_but(&mut self, roots: impl Iterator<Item=usize>) {
-		let mut set = FnvHashSet::default();
-		set.insert(self.i_key);
-		set.insert(self.k_key);
-		set.insert(self.s_key);
-		for root in roots {
-			for key in Traverser::new(self, TraversalOrder::LeftmostOutermost, root) {
-				set.insert(key);
+	fn report(&mut self, r: &Reduction, key: usize, folds: &Folds) {
+		// a memoized normal form is reached without steps, and so is not traced
+		if !self.trace && (r.steps > 0 || r.memoized) {
+			println!("-> {}", self.tb.term_string_folded(key, folds));
+		}
+		if self.decode && r.stop == Stop::NormalForm {
+			if let Some(annotation) = self.tb.annotation(key, &Budget::steps(DECODE_STEPS)) {
+				println!("   {}", annotation);
 			}
 		}
-		self.slab_locator.retain(|key, _t| set.contains(&key));
-		self.slab.retain(|key, _t| set.contains(&key));
-	}
-
-
-
-	fn define(&mut self, varname: char, key: usize) {
-		self.defined.insert(varname, key);
-	}
-}
-
-enum TraversalOrder {
-	LeftmostOutermost,
-}
-
-struct Traverser<'a> {
-	tb: &'a TermBase,
-	traversal_order: TraversalOrder,
-	stack: Vec<usize>,
-}
-impl<'a> Traverser<'a> {
-	pub fn new(tb: &'a TermBase, traversal_order: TraversalOrder, root: usize) -> Traverser<'a> {
-		Traverser {
-			tb,
-			traversal_order,
-			stack: vec![root],
+		if r.stop != Stop::NormalForm {
+			println!("...");
 		}
+		let memoized = if r.memoized { ", memoized" } else { "" };
+		println!("{} after {} steps ({}{})", r.stop, r.steps, self.order, memoized);
 	}
 }
 
-impl<'a> Iterator for Traverser<'a>{
-    type Item = usize;
-    fn next(&mut self) -> Option<Self::Item> {
-    	if let Some(key) = self.stack.pop() {
-    		match self.tb.slab[key].term {
-	        	Term::Ap(l, r) => {
-	        		match self.traversal_order {
-	        			TraversalOrder::LeftmostOutermost => {
-	        				self.stack.push(r);
-			        		self.stack.push(l);
-			        		Some(key)
-	        			}
-	        		}
-	        	},
-	        	Term::Abs(v, k) => {
-	        		match self.traversal_order {
-	        			TraversalOrder::LeftmostOutermost => {
-	        				self.stack.push(k);
-	        				Some(key)
-	        			}
-	        		}
-	        	},
-	        	Term::I |
-	        	Term::K |
-	        	Term::S |
-	        	Term::Var(_) => Some(key),
-	        }
-    	} else {
-    		None
-    	}
-    }
-}
-
-struct Parser<'a, 'b> {
-	tb: &'a mut TermBase,
-	at: usize,
-	abs: Vec<char>,
-	src: &'b [u8],
+fn fresh_variables(n: usize) -> String {
+	format!("{} fresh variable{}", n, if n == 1 { "" } else { "s" })
 }
 
-impl<'a, 'b> Parser<'a, 'b> {
-	pub fn parse(tb: &'a mut TermBase, src: &'b [u8]) -> Option<usize> {
-		let mut parser = Parser {
-			tb,
-			at: 0,
-			src,
-			abs: vec![],
-		};
-		let x = parser.parse_term();
-		if parser.at != src.len() {
-			return None
-		} else {
-			x
-		}
-	}
-
-	fn push_raw_term(&mut self, o: &mut Option<usize>, t: Term, abs_vec: &mut Vec<char>) {
-		let n = self.tb.find_and_ref_up(t);
-		self.push_raw_term2(o, n, abs_vec)
-	}
-
-	fn push_raw_term2(&mut self, o: &mut Option<usize>, n: usize, abs_vec: &mut Vec<char>) {
-		let mut x = if let Some(mut prev) = *o {
-			let a = Term::Ap(prev, n);
-			let q = self.tb.find_and_ref_up(a);
-			q
-		} else {
-			n
-		};
-		for v in abs_vec.drain(..).rev() {
-			let new_term = Term::Abs(v, x);
-			x = self.tb.find_and_ref_up(new_term);
-		}
-		*o = Some(x)	
+// `name = term`, but not `a == b`
+fn is_definition(line1: &str) -> bool {
+	let mut it = line1.splitn(2, '=');
+	match (it.next(), it.next()) {
+		(Some(name), Some(rest)) => is_ident(name.trim()) && !rest.starts_with('='),
+		_ => false,
 	}
+}
 
-	fn parse_term(&mut self) -> Option<usize> {
-		let mut o: Option<usize> = None;
-		let mut abs_vec = vec![];
-		let mut abs = false;
-		loop {
-			let c = self.src[self.at] as char;
-			self.at += 1;
-			if abs {
-				match c {
-					' ' => (),
-					'('|')'|'S'|'K'|'I'|'[' => return None,
-				 	']' => abs = false,
-					 v  => {
-					 	if let Some(&key) = self.tb.defined.get(&v) {
-					 		return None;
-					 	} else {
-					 		abs_vec.push(v);
-					 	}				 	
-					},
-				}
-			} else {
-				match c {
-					'[' => abs = true,
-					' ' => (),
-					'(' => {
-						if let Some(x) = self.parse_term() {
-							self.push_raw_term2(&mut o, x, &mut abs_vec);
-						} else {
-							return None;
-						}
-					},
-					']' => return None,
-					')' => return o,
-					'S' => self.push_raw_term(&mut o, Term::S, &mut abs_vec),
-					'K' => self.push_raw_term(&mut o, Term::K, &mut abs_vec),
-					'I' => self.push_raw_term(&mut o, Term::I, &mut abs_vec),
-					 v  => {
-					 	if let Some(&key) = self.tb.defined.get(&v) {
-					 		println!("MATCHED defined TERM {:?}", v);
-					 		self.push_raw_term2(&mut o, key, &mut abs_vec);
-					 	} else {
-					 		self.push_raw_term(&mut o, Term::Var(v), &mut abs_vec);
-					 	}				 	
-					},
-				};
-			}
-			if self.at >= self.src.len() {
-				break;
-			}
-		}
-		o
+// loads the prelude into a scratch TermBase and runs its checks
+fn verify_prelude() {
+	let mut tb = TermBase::new();
+	match tb.load_prelude().and_then(|_| tb.check_prelude(&Budget::steps(PRELUDE_STEPS))) {
+		Ok(checked) => println!("prelude verified ({} checks)", checked),
+		Err(e) => println!("prelude check failed: {}", e),
 	}
 }
 
 fn main() {
-	let mut tb = TermBase::new();
+	verify_prelude();
+	let mut repl = Repl::new();
 	let stdin = io::stdin();
-    let mut iterator = stdin.lock().lines();
-    'outer: loop {
-    	let line1 = iterator.next().unwrap().unwrap();
-    	let bytes = line1.as_bytes();
-    	// if bytes.len()
-    	if bytes.len() >= 3 && bytes[1] as char == '=' {
-    		if let Some(mut k) = Parser::parse(&mut tb, &bytes[2..]) {
-    			println!("DEFINING");
-    			tb.define(bytes[0] as char, k);
-    			println!("defined <{},{}>", bytes[0] as char, k);
-    		}
-    	} else if bytes.len() >= 3 && bytes[0] as char == '>' && bytes[1] as char == '*' {
-    		if let Some(mut k) = Parser::parse(&mut tb, &bytes[2..]) {
-    			println!("REWRITING");
-				print!("   ");
-				tb.print_term(k);
-				println!();
-				for _ in 0..32 {
-					if tb.normal_form(k) {
-						continue 'outer;
-					}
-					k = tb.outermost_leftmost(k);
-					print!("-> ");
-					tb.print_term(k);
-					println!();
-				}
-				println!("...");
-			}
-    	} else {
-    		println!("Failed to understand");
-    	}
-    	println!("defined {:?}", tb.defined.iter().collect::<Vec<_>>());
-    	tb.gc_all_but_defined();
+	let mut iterator = stdin.lock().lines();
+	// collecting only once the heap has doubled lets memoized normal forms outlive their line
+	let mut gc_threshold = GC_MIN_NODES;
+	while let Some(Ok(line1)) = iterator.next() {
+		repl.line(&line1);
+		println!("defined {:?}", repl.tb.definitions().collect::<Vec<_>>());
+		if repl.tb.len() > gc_threshold {
+			let reclaimed = repl.tb.gc_all_but_defined();
+			println!("gc reclaimed {} nodes, {} live", reclaimed, repl.tb.len());
+			gc_threshold = GC_MIN_NODES.max(2 * repl.tb.len());
+		}
 	}
 }
@@ -0,0 +1,382 @@
+use std::fmt;
+
+use symbol::{self, Sym};
+use term::{Term, TermBase};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+	pub pos: usize, // byte offset into the source
+	pub expected: &'static str,
+	pub message: String,
+}
+impl ParseError {
+	fn new(pos: usize, expected: &'static str, message: impl Into<String>) -> Self {
+		ParseError {
+			pos,
+			expected,
+			message: message.into(),
+		}
+	}
+
+	// the source line with a caret under the offending column
+	pub fn caret(&self, src: &str) -> String {
+		let col = src[..self.pos.min(src.len())].chars().count();
+		format!("{}\n{}^", src, " ".repeat(col))
+	}
+}
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} at column {} (expected {})", self.message, self.pos + 1, self.expected)
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Token<'s> {
+	Open,
+	Close,
+	BinderOpen,
+	BinderClose,
+	Equals,
+	Semicolon,
+	Ident(&'s str),
+	End,
+}
+
+impl<'s> fmt::Display for Token<'s> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Token::Open => write!(f, "`(`"),
+			Token::Close => write!(f, "`)`"),
+			Token::BinderOpen => write!(f, "`[`"),
+			Token::BinderClose => write!(f, "`]`"),
+			Token::Equals => write!(f, "`=`"),
+			Token::Semicolon => write!(f, "`;`"),
+			Token::Ident(s) => write!(f, "`{}`", s),
+			Token::End => write!(f, "end of input"),
+		}
+	}
+}
+
+struct Lexer<'s> {
+	src: &'s str,
+	at: usize,
+}
+impl<'s> Lexer<'s> {
+	fn skip_space(&mut self) {
+		let rest = &self.src[self.at..];
+		self.at += rest.len() - rest.trim_start().len();
+	}
+
+	// the next token and where it starts, without consuming it
+	fn peek(&mut self) -> Result<(usize, Token<'s>), ParseError> {
+		self.skip_space();
+		let rest = &self.src[self.at..];
+		let c = match rest.chars().next() {
+			Some(c) => c,
+			None => return Ok((self.at, Token::End)),
+		};
+		let token = match c {
+			'(' => Token::Open,
+			')' => Token::Close,
+			'[' => Token::BinderOpen,
+			']' => Token::BinderClose,
+			'=' => Token::Equals,
+			';' => Token::Semicolon,
+			_ => match symbol::ident_len(rest) {
+				Some(len) => Token::Ident(&rest[..len]),
+				None => return Err(ParseError::new(self.at, "a term", format!("unexpected `{}`", c))),
+			},
+		};
+		Ok((self.at, token))
+	}
+
+	fn next(&mut self) -> Result<(usize, Token<'s>), ParseError> {
+		let (pos, token) = self.peek()?;
+		self.at += match token {
+			Token::End => 0,
+			Token::Ident(s) => s.len(),
+			_ => 1,
+		};
+		Ok((pos, token))
+	}
+}
+
+pub(crate) struct Parser<'a, 's> {
+	tb: &'a mut TermBase,
+	lexer: Lexer<'s>,
+	defining: Option<&'s str>, // the name a definition may refer to before it exists
+	locals: Vec<&'s str>, // names read as variables even if defined, within a `rec` group
+	rule: Option<(&'s str, u32)>, // the combinator a rule declares, which its right-hand side may use
+}
+
+impl<'a, 's> Parser<'a, 's> {
+	fn new(tb: &'a mut TermBase, src: &'s str) -> Self {
+		Parser {
+			tb,
+			lexer: Lexer { src, at: 0 },
+			defining: None,
+			locals: vec![],
+			rule: None,
+		}
+	}
+
+	pub fn parse(tb: &'a mut TermBase, src: &'s str) -> Result<usize, ParseError> {
+		let mut parser = Parser::new(tb, src);
+		let x = parser.parse_term()?;
+		parser.expect_end()?;
+		Ok(x)
+	}
+
+	// `name = term`
+	pub fn parse_definition(tb: &'a mut TermBase, src: &'s str) -> Result<(Sym, usize), ParseError> {
+		let mut parser = Parser::new(tb, src);
+		let name = parser.parse_name()?;
+		parser.defining = Some(name);
+		let x = parser.parse_term()?;
+		parser.expect_end()?;
+		Ok((parser.tb.symbols.intern(name), x))
+	}
+
+	// `name = term; name = term; ..`, where every term sees every name as a variable
+	pub fn parse_group(tb: &'a mut TermBase, src: &'s str) -> Result<Vec<(Sym, usize)>, ParseError> {
+		let mut parser = Parser::new(tb, src);
+		parser.locals = parser.group_names()?;
+		let mut group = vec![];
+		loop {
+			let name = parser.parse_name()?;
+			let x = parser.parse_term()?;
+			group.push((parser.tb.symbols.intern(name), x));
+			match parser.lexer.next()? {
+				(_, Token::Semicolon) => (),
+				(_, Token::End) => return Ok(group),
+				(pos, _) => return Err(ParseError::new(pos, "`;` or end of input", "unmatched `)`")),
+			}
+		}
+	}
+
+	// `NAME x1 .. xn = term`, declaring the combinator `id` unless NAME already has one.
+	// Returns the name, the pattern variables and the right-hand side, compiled
+	pub fn parse_rule(tb: &'a mut TermBase, src: &'s str) -> Result<(Sym, Vec<Sym>, usize), ParseError> {
+		let mut parser = Parser::new(tb, src);
+		let name = match parser.lexer.next()? {
+			(_, Token::Ident(name)) if parser.tb.rule(name).is_some() => name,
+			(pos, Token::Ident(name)) if parser.tb.is_keyword(name) => {
+				return Err(ParseError::new(pos, "a name", format!("cannot redefine `{}`", name)));
+			},
+			(pos, Token::Ident(name)) if parser.tb.definition(name).is_some() => {
+				return Err(ParseError::new(pos, "a name", format!("`{}` is already defined", name)));
+			},
+			(_, Token::Ident(name)) => name,
+			(pos, _) => return Err(ParseError::new(pos, "a name", "rule without a name")),
+		};
+		let mut params: Vec<&'s str> = vec![];
+		loop {
+			match parser.lexer.next()? {
+				(pos, Token::Equals) if params.is_empty() => {
+					return Err(ParseError::new(pos, "a variable", "rule without pattern variables"));
+				},
+				(_, Token::Equals) => break,
+				(pos, Token::Ident(v)) if v == name || parser.tb.is_keyword(v) || parser.tb.definition(v).is_some() => {
+					return Err(ParseError::new(pos, "a variable or `=`", format!("`{}` cannot be a pattern variable", v)));
+				},
+				(pos, Token::Ident(v)) if params.contains(&v) => {
+					return Err(ParseError::new(pos, "a new variable", format!("`{}` appears twice, but rules must be left-linear", v)));
+				},
+				(_, Token::Ident(v)) => params.push(v),
+				(pos, _) => return Err(ParseError::new(pos, "a variable or `=`", "malformed rule")),
+			}
+		}
+		let rhs_at = parser.lexer.at;
+		let id = parser.tb.rule(name).unwrap_or(parser.tb.rules.len() as u32);
+		parser.rule = Some((name, id));
+		let rhs = parser.parse_term()?;
+		parser.expect_end()?;
+		// free of abstractions, any variable left is free
+		let rhs = parser.tb.compile(rhs);
+		let params: Vec<Sym> = params.iter().map(|v| parser.tb.symbols.intern(v)).collect();
+		if let Some(&v) = parser.tb.vars(rhs).iter().find(|v| !params.contains(v)) {
+			let v = parser.tb.name(v);
+			let mut lexer = Lexer { src, at: rhs_at };
+			let pos = loop {
+				match lexer.next()? {
+					(pos, Token::Ident(u)) if u == v => break pos,
+					(pos, Token::End) => break pos,
+					_ => (),
+				}
+			};
+			return Err(ParseError::new(pos, "a pattern variable", format!("`{}` is not bound by the pattern", v)));
+		}
+		Ok((parser.tb.symbols.intern(name), params, rhs))
+	}
+
+	// the names a group defines, looking ahead to the end
+	fn group_names(&self) -> Result<Vec<&'s str>, ParseError> {
+		let mut lexer = Lexer { src: self.lexer.src, at: self.lexer.at };
+		let mut names: Vec<&'s str> = vec![];
+		let mut start = true;
+		loop {
+			match lexer.next()? {
+				(_, Token::End) => return Ok(names),
+				(_, Token::Semicolon) => start = true,
+				(pos, Token::Ident(name)) if start => {
+					if names.contains(&name) {
+						return Err(ParseError::new(pos, "a new name", format!("`{}` is defined twice", name)));
+					}
+					names.push(name);
+					start = false;
+				},
+				_ => start = false,
+			}
+		}
+	}
+
+	// `name =`
+	fn parse_name(&mut self) -> Result<&'s str, ParseError> {
+		let name = match self.lexer.next()? {
+			(pos, Token::Ident(name)) if self.tb.is_keyword(name) => {
+				return Err(ParseError::new(pos, "a name", format!("cannot redefine `{}`", name)));
+			},
+			(_, Token::Ident(name)) => name,
+			(pos, _) => return Err(ParseError::new(pos, "a name", "definition without a name")),
+		};
+		match self.lexer.next()? {
+			(_, Token::Equals) => Ok(name),
+			(pos, _) => Err(ParseError::new(pos, "`=`", "malformed definition")),
+		}
+	}
+
+	fn is_defined(&self, name: &str) -> bool {
+		!self.locals.contains(&name) && (self.defining == Some(name) || self.tb.definition(name).is_some())
+	}
+
+	fn expect_end(&mut self) -> Result<(), ParseError> {
+		match self.lexer.peek()? {
+			(_, Token::End) => Ok(()),
+			(pos, Token::Semicolon) => Err(ParseError::new(pos, "end of input", "unexpected `;`")),
+			// parse_term only stops early at a `)` or `;`
+			(pos, _) => Err(ParseError::new(pos, "end of input", "unmatched `)`")),
+		}
+	}
+
+	fn push_raw_term(&mut self, o: &mut Option<usize>, t: Term, abs_vec: &mut Vec<Sym>) {
+		let n = self.tb.find_and_ref_up(t);
+		self.push_raw_term2(o, n, abs_vec)
+	}
+
+	fn push_raw_term2(&mut self, o: &mut Option<usize>, n: usize, abs_vec: &mut Vec<Sym>) {
+		let mut x = if let Some(prev) = *o {
+			self.tb.ap(prev, n)
+		} else {
+			n
+		};
+		for v in abs_vec.drain(..).rev() {
+			x = self.tb.find_and_ref_up(Term::Abs(v, x));
+		}
+		*o = Some(x)
+	}
+
+	// the variables of a binder, after its `[`
+	fn parse_binder(&mut self, abs_vec: &mut Vec<Sym>) -> Result<(), ParseError> {
+		let mut empty = true;
+		loop {
+			match self.lexer.next()? {
+				(pos, Token::BinderClose) if empty => {
+					return Err(ParseError::new(pos, "a variable", "empty binder"));
+				},
+				(_, Token::BinderClose) => return Ok(()),
+				(pos, Token::End) => return Err(ParseError::new(pos, "`]`", "unclosed binder")),
+				(pos, Token::Ident(v)) => {
+					if self.tb.is_keyword(v) {
+						return Err(ParseError::new(pos, "a variable or `]`", format!("cannot bind `{}`", v)));
+					}
+					if self.tb.definition(v).is_some() {
+						return Err(ParseError::new(pos, "a variable or `]`", format!("cannot bind defined `{}`", v)));
+					}
+					abs_vec.push(self.tb.symbols.intern(v));
+				},
+				(pos, t) => {
+					return Err(ParseError::new(pos, "a variable or `]`", format!("unexpected {} in binder", t)));
+				},
+			}
+			empty = false;
+		}
+	}
+
+	// a sequence of applied terms, up to the end of input or an unconsumed `)`
+	fn parse_term(&mut self) -> Result<usize, ParseError> {
+		let (start, _) = self.lexer.peek()?;
+		let mut o: Option<usize> = None;
+		let mut abs_vec = vec![];
+		loop {
+			let (pos, token) = self.lexer.peek()?;
+			match token {
+				Token::End |
+				Token::Close |
+				Token::Semicolon => break,
+				Token::BinderOpen => {
+					self.lexer.next()?;
+					self.parse_binder(&mut abs_vec)?;
+				},
+				Token::Open => {
+					self.lexer.next()?;
+					let x = self.parse_term()?;
+					if self.lexer.next()?.1 != Token::Close {
+						return Err(ParseError::new(pos, "`)`", "unclosed `(`"));
+					}
+					self.push_raw_term2(&mut o, x, &mut abs_vec);
+				},
+				Token::BinderClose => return Err(ParseError::new(pos, "a term", "`]` outside a binder")),
+				Token::Equals => return Err(ParseError::new(pos, "a term", "unexpected `=`")),
+				Token::Ident(v) => {
+					self.lexer.next()?;
+					if let Some((_, id)) = self.rule.filter(|&(name, _)| name == v) {
+						self.push_raw_term(&mut o, Term::Comb(id), &mut abs_vec);
+					} else if let Some(t) = self.tb.primitive(v) {
+						self.push_raw_term(&mut o, t, &mut abs_vec);
+					} else if self.is_defined(v) {
+						let name = self.tb.symbols.intern(v);
+						self.push_raw_term(&mut o, Term::Const(name), &mut abs_vec);
+					} else {
+						let v = self.tb.symbols.intern(v);
+						self.push_raw_term(&mut o, Term::Var(v), &mut abs_vec);
+					}
+				},
+			}
+		}
+		if !abs_vec.is_empty() {
+			let (pos, _) = self.lexer.peek()?;
+			return Err(ParseError::new(pos, "a term", "binder without a body"));
+		}
+		o.ok_or_else(|| ParseError::new(start, "a term", "empty term"))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// the position, what was expected and the caret line
+	fn error(src: &str) -> (usize, &'static str, String) {
+		let mut tb = TermBase::new();
+		let e = if src.contains('=') { tb.parse_definition(src).map(|_| ()) } else { tb.parse(src).map(|_| ()) };
+		let e = e.unwrap_err();
+		let caret = e.caret(src);
+		(e.pos, e.expected, caret.lines().last().unwrap().to_owned())
+	}
+
+	#[test]
+	fn errors_point_at_the_problem() {
+		assert_eq!(error(""), (0, "a term", "^".to_owned()));
+		assert_eq!(error("x="), (2, "a term", "  ^".to_owned()));
+		assert_eq!(error("(S K"), (0, "`)`", "^".to_owned()));
+		assert_eq!(error("S K)"), (3, "end of input", "   ^".to_owned()));
+		assert_eq!(error("[x"), (2, "`]`", "  ^".to_owned()));
+		assert_eq!(error("[]x"), (1, "a variable", " ^".to_owned()));
+	}
+
+	#[test]
+	fn caret_repeats_the_source() {
+		let e = TermBase::new().parse("S K)").unwrap_err();
+		assert_eq!(e.caret("S K)"), "S K)\n   ^");
+	}
+}
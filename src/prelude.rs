@@ -0,0 +1,133 @@
+use fnv::FnvHashMap;
+
+use reduce::{Budget, Stop};
+use symbol::Sym;
+use term::{Term, TermBase, Unfold};
+use traverse::TraversalOrder;
+
+// Church-encoded data and named combinators, loaded on demand with `:prelude`
+pub const PRELUDE: &str = include_str!("prelude.txt");
+
+// the numbered lines of the prelude holding a definition or check, without comments
+fn lines() -> impl Iterator<Item = (usize, &'static str)> {
+	PRELUDE.lines()
+	.enumerate()
+	.map(|(i, line)| (i + 1, line.split('#').next().unwrap().trim()))
+	.filter(|&(_, line)| !line.is_empty())
+}
+
+impl TermBase {
+	// defines every name of the prelude, except those the base has as primitives. It is parsed
+	// apart from the session, whose definitions would otherwise clash with the prelude's binders,
+	// and copied over only once all of it has parsed
+	pub fn load_prelude(&mut self) -> Result<Vec<Sym>, String> {
+		let mut scratch = TermBase::new();
+		scratch.set_base(self.base())?;
+		let mut parsed = vec![];
+		for (n, line) in lines().filter(|(_, line)| !line.contains("==")) {
+			if self.is_keyword(line.split('=').next().unwrap().trim()) {
+				continue;
+			}
+			parsed.push(scratch.parse_definition(line).map_err(|e| format!("prelude line {}: {}", n, e))?);
+		}
+		let mut memo = FnvHashMap::default();
+		let mut names = vec![];
+		for (name, key) in parsed {
+			let key = self.copy_from(&scratch, key, &mut memo);
+			self.define(scratch.name(name), key)?;
+			names.push(self.symbols.intern(scratch.name(name)));
+		}
+		Ok(names)
+	}
+
+	// `key` of `other`, rebuilt here with the same names
+	fn copy_from(&mut self, other: &TermBase, key: usize, memo: &mut FnvHashMap<usize, usize>) -> usize {
+		if let Some(&k) = memo.get(&key) {
+			return k;
+		}
+		let k = match other.term(key) {
+			Term::Ap(l, r) => {
+				let l = self.copy_from(other, l, memo);
+				let r = self.copy_from(other, r, memo);
+				self.ap(l, r)
+			},
+			Term::Abs(v, body) => {
+				let body = self.copy_from(other, body, memo);
+				let v = self.symbols.intern(other.name(v));
+				self.find_and_ref_up(Term::Abs(v, body))
+			},
+			Term::Lam(body) => {
+				let body = self.copy_from(other, body, memo);
+				self.find_and_ref_up(Term::Lam(body))
+			},
+			Term::Var(v) => {
+				let v = self.symbols.intern(other.name(v));
+				self.find_and_ref_up(Term::Var(v))
+			},
+			Term::Const(v) => {
+				let v = self.symbols.intern(other.name(v));
+				self.find_and_ref_up(Term::Const(v))
+			},
+			Term::Comb(_) => unreachable!("the prelude declares no rules"),
+			t => self.find_and_ref_up(t),
+		};
+		memo.insert(key, k);
+		k
+	}
+
+	// reduces both sides of each prelude check to beta normal form, returning how many hold.
+	// Expects the prelude loaded
+	pub fn check_prelude(&mut self, budget: &Budget) -> Result<usize, String> {
+		let unfold = self.unfold();
+		self.set_unfold(Unfold::Lambda);
+		let checked = self.check_prelude_lines(budget);
+		self.set_unfold(unfold);
+		checked
+	}
+
+	fn check_prelude_lines(&mut self, budget: &Budget) -> Result<usize, String> {
+		let mut checked = 0;
+		for (n, line) in lines().filter(|(_, line)| line.contains("==")) {
+			let mut sides = vec![];
+			for side in line.splitn(2, "==") {
+				let key = self.parse(side).map_err(|e| format!("prelude line {}: {}", n, e))?;
+				let key = self.lambda(key);
+				let r = self.reduce(key, TraversalOrder::LeftmostOutermost, budget, |_, _| ());
+				if r.stop != Stop::NormalForm {
+					return Err(format!("prelude line {}: `{}` {}", n, side.trim(), r.stop));
+				}
+				sides.push(r.key);
+			}
+			if sides[0] != sides[1] {
+				return Err(format!(
+					"prelude line {}: `{}` but `{}`",
+					n,
+					self.term_string(sides[0]),
+					self.term_string(sides[1]),
+				));
+			}
+			checked += 1;
+		}
+		Ok(checked)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn loads_beside_definitions_of_its_binders() {
+		let mut tb = TermBase::new();
+		tb.parse_definition("x = S").unwrap();
+		tb.parse_definition("f = K").unwrap();
+		let names = tb.load_prelude().unwrap();
+		assert!(names.len() > 20);
+		assert_eq!(tb.term_string(tb.definition("x").unwrap()), "S");
+		let k = tb.parse("succ two f x").unwrap();
+		let k = tb.lambda(k);
+		let r = tb.normalize(k, TraversalOrder::LeftmostOutermost, &Budget::steps(1000));
+		let expected = tb.parse("K(K(K S))").unwrap();
+		assert_eq!((r.stop, r.key), (Stop::NormalForm, expected));
+	}
+}
@@ -0,0 +1,257 @@
+use fnv::{FnvHashMap, FnvHashSet};
+
+use refcount::RcTermBase;
+use symbol::{self, Sym};
+use term::{Term, TermBase};
+
+#[derive(Copy, Clone, PartialEq)]
+enum Last {
+	Punct,
+	Lower, // a lowercase identifier
+	Upper, // an uppercase identifier, like S or Y'
+}
+
+// Subterms to print by the name of the definition they equal. Made by TermBase::folds.
+// After a collection only the definitions' own keys are trusted, as the others may be reused
+#[derive(Debug, Default)]
+pub struct Folds {
+	names: FnvHashMap<usize, Sym>,
+	collections: usize,
+}
+
+// Writes terms back in the syntax the parser reads. Identifiers are separated
+// by spaces, except for runs of uppercase ones like `SKK`.
+// Nameless lambdas get the first of x, y, z, w, u, v, x1, y1, .. not otherwise in sight.
+pub(crate) struct Printer<'a> {
+	tb: &'a TermBase,
+	rc: Option<&'a RcTermBase>, // where the nodes are, if not in `tb`, which still names things
+	out: &'a mut String,
+	last: Last,
+	free: FnvHashSet<Sym>, // the variables of the printed term
+	bound: Vec<String>, // names given to the enclosing lambdas, innermost last
+	folds: Option<&'a Folds>,
+}
+impl<'a> Printer<'a> {
+	pub fn new(tb: &'a TermBase, out: &'a mut String, root: usize, folds: Option<&'a Folds>) -> Self {
+		Printer {
+			tb,
+			rc: None,
+			out,
+			last: Last::Punct,
+			free: tb.vars(root),
+			bound: vec![],
+			folds,
+		}
+	}
+
+	// the rc store holds no nameless lambdas, so needs no fresh names, and no folds
+	pub fn new_rc(rc: &'a RcTermBase, tb: &'a TermBase, out: &'a mut String) -> Self {
+		Printer {
+			tb,
+			rc: Some(rc),
+			out,
+			last: Last::Punct,
+			free: FnvHashSet::default(),
+			bound: vec![],
+			folds: None,
+		}
+	}
+
+	fn node(&self, key: usize) -> Term {
+		match self.rc {
+			Some(rc) => rc.term(key),
+			None => self.tb.term(key),
+		}
+	}
+
+	fn folded(&self, key: usize) -> Option<Sym> {
+		let folds = self.folds?;
+		let name = *folds.names.get(&key)?;
+		if folds.collections == self.tb.collections || self.tb.definition(self.tb.name(name)) == Some(key) {
+			Some(name)
+		} else {
+			None
+		}
+	}
+
+	fn fresh_name(&self) -> String {
+		symbol::fresh_names().find(|name| {
+			!self.bound.contains(name)
+			&& self.tb.definition(name).is_none()
+			&& self.tb.symbols().get(name).is_none_or(|sym| !self.free.contains(&sym))
+		})
+		.unwrap()
+	}
+
+	fn token(&mut self, tok: &str) {
+		let next = match tok.chars().next() {
+			Some(c) if symbol::is_lower_start(c) => Last::Lower,
+			Some(c) if c.is_ascii_uppercase() => Last::Upper,
+			_ => Last::Punct,
+		};
+		if next != Last::Punct && self.last != Last::Punct && !(next == Last::Upper && self.last == Last::Upper) {
+			self.out.push(' ');
+		}
+		self.out.push_str(tok);
+		self.last = next;
+	}
+
+	fn maybe_parens(&mut self, key: usize) {
+		if self.node(key).atomic() || self.folded(key).is_some() {
+			self.term(key);
+		} else {
+			self.token("(");
+			self.term(key);
+			self.token(")");
+		}
+	}
+
+	pub fn term(&mut self, key: usize) {
+		let tb = self.tb;
+		if let Some(name) = self.folded(key) {
+			return self.token(tb.name(name));
+		}
+		match self.node(key) {
+			Term::Ap(l, r) => {
+				self.term(l);
+				self.maybe_parens(r);
+			},
+			Term::Abs(_, _) => {
+				self.token("[");
+				let mut k = key;
+				while let Term::Abs(v, term) = self.node(k) {
+					self.token(tb.name(v));
+					k = term;
+					if self.folded(k).is_some() {
+						break;
+					}
+				}
+				self.token("]");
+				self.maybe_parens(k);
+			},
+			Term::Lam(_) => {
+				self.token("[");
+				let depth = self.bound.len();
+				let mut k = key;
+				while let Term::Lam(term) = self.node(k) {
+					let name = self.fresh_name();
+					self.token(&name);
+					self.bound.push(name);
+					k = term;
+					if self.folded(k).is_some() {
+						break;
+					}
+				}
+				self.token("]");
+				self.maybe_parens(k);
+				self.bound.truncate(depth);
+			},
+			Term::Idx(i) => match self.bound.len().checked_sub(i as usize + 1) {
+				Some(at) => {
+					let name = self.bound[at].clone();
+					self.token(&name);
+				},
+				None => self.token(&format!("#{}", i)), // bound outside the printed term
+			},
+			Term::I => self.token("I"),
+			Term::K => self.token("K"),
+			Term::S => self.token("S"),
+			Term::B => self.token("B"),
+			Term::C => self.token("C"),
+			Term::W => self.token("W"),
+			Term::X => self.token("X"),
+			Term::Bulk(kind, n) => self.token(&format!("{}{}", kind, n)),
+			Term::Comb(id) => self.token(tb.rule_name(id)),
+			Term::Var(v) |
+			Term::Const(v) => self.token(tb.name(v)),
+		}
+	}
+}
+
+impl TermBase {
+	// each definition, as written, compiled and in de Bruijn form, wherever that is not atomic.
+	// A form shared by two definitions, like KI by zero and false, is left unfolded
+	pub fn folds(&mut self) -> Folds {
+		let mut defined: Vec<(Sym, usize)> = self.definitions()
+		.map(|(name, key)| (self.symbols.get(name).unwrap(), key))
+		.collect();
+		defined.sort();
+		let mut folds = Folds {
+			names: FnvHashMap::default(),
+			collections: self.collections,
+		};
+		let mut shared = FnvHashSet::default();
+		for (name, key) in defined {
+			let compiled = self.compile(key);
+			let lambda = self.lambda(key);
+			for &k in [key, compiled, lambda].iter() {
+				if self.term(k).atomic() {
+					continue;
+				}
+				if *folds.names.entry(k).or_insert(name) != name {
+					shared.insert(k);
+				}
+			}
+		}
+		folds.names.retain(|k, _| !shared.contains(k));
+		folds
+	}
+
+	pub fn write_term(&self, key: usize, out: &mut String) {
+		Printer::new(self, out, key, None).term(key);
+	}
+
+	pub fn write_term_folded(&self, key: usize, folds: &Folds, out: &mut String) {
+		Printer::new(self, out, key, Some(folds)).term(key);
+	}
+
+	pub fn term_string_folded(&self, key: usize, folds: &Folds) -> String {
+		let mut s = String::new();
+		self.write_term_folded(key, folds, &mut s);
+		s
+	}
+
+	pub fn print_term_folded(&self, key: usize, folds: &Folds) {
+		print!("{}", self.term_string_folded(key, folds));
+	}
+
+	pub fn term_string(&self, key: usize) -> String {
+		let mut s = String::new();
+		self.write_term(key, &mut s);
+		s
+	}
+
+	pub fn print_term(&self, key: usize) {
+		print!("{}", self.term_string(key));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn atoms_are_never_folded() {
+		let mut tb = TermBase::new();
+		tb.load_prelude().unwrap();
+		let folds = tb.folds();
+		for &(src, printed) in [("K", "K"), ("I", "I"), ("SKK", "SKK"), ("K I", "KI")].iter() {
+			let k = tb.parse(src).unwrap();
+			let k = tb.compile(k);
+			assert_eq!(tb.term_string_folded(k, &folds), printed);
+		}
+	}
+
+	#[test]
+	fn forms_shared_by_definitions_are_not_folded() {
+		let mut tb = TermBase::new();
+		tb.load_prelude().unwrap();
+		let folds = tb.folds();
+		// zero, false and nil all compile to KI
+		for &(src, printed) in [("[f x]x", "KI"), ("[x y]y", "KI"), ("[f x](f(f x))", "succ I")].iter() {
+			let k = tb.parse(src).unwrap();
+			let k = tb.compile(k);
+			assert_eq!(tb.term_string_folded(k, &folds), printed);
+		}
+	}
+}
@@ -0,0 +1,313 @@
+use fnv::FnvHashMap;
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use term::TermBase;
+use traverse::TraversalOrder;
+
+// Limits on a single normalization. `None` means unlimited.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Budget {
+	pub max_steps: Option<usize>,
+	pub max_nodes: Option<usize>,
+	pub timeout: Option<Duration>,
+	pub max_depth: Option<usize>, // of nested abstractions and arguments, when reading terms back
+	pub detect_cycles: bool, // stop when a term is revisited
+}
+impl Default for Budget {
+	fn default() -> Self {
+		Budget {
+			max_steps: None,
+			max_nodes: None,
+			timeout: None,
+			max_depth: None,
+			detect_cycles: true,
+		}
+	}
+}
+impl Budget {
+	pub fn unlimited() -> Self {
+		Budget::default()
+	}
+
+	pub fn steps(max_steps: usize) -> Self {
+		Budget {
+			max_steps: Some(max_steps),
+			..Budget::default()
+		}
+	}
+
+	// applies settings like `steps=1000 nodes=none time=2s depth=20 cycles=off` (or `time=250ms`)
+	pub fn update(&mut self, spec: &str) -> Result<(), String> {
+		for setting in spec.split(|c: char| c.is_whitespace() || c == ',').filter(|s| !s.is_empty()) {
+			let mut it = setting.splitn(2, '=');
+			let (name, value) = match (it.next(), it.next()) {
+				(Some(name), Some(value)) => (name.trim(), value.trim()),
+				_ => return Err(format!("expected `name=value`, got `{}`", setting)),
+			};
+			let none = value == "none" || value == "-";
+			match name {
+				"steps" if none => self.max_steps = None,
+				"steps" => self.max_steps = Some(parse_count(value)?),
+				"nodes" if none => self.max_nodes = None,
+				"nodes" => self.max_nodes = Some(parse_count(value)?),
+				"time" if none => self.timeout = None,
+				"time" => self.timeout = Some(parse_duration(value)?),
+				"depth" if none => self.max_depth = None,
+				"depth" => self.max_depth = Some(parse_count(value)?),
+				"cycles" => self.detect_cycles = match value {
+					"on" => true,
+					"off" => false,
+					_ => return Err(format!("expected cycles=on or cycles=off, got `{}`", value)),
+				},
+				_ => return Err(format!("unknown limit `{}`, expected steps, nodes, time, depth or cycles", name)),
+			}
+		}
+		Ok(())
+	}
+}
+impl fmt::Display for Budget {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.max_steps {
+			Some(n) => write!(f, "steps={}", n)?,
+			None => write!(f, "steps=none")?,
+		}
+		match self.max_nodes {
+			Some(n) => write!(f, " nodes={}", n)?,
+			None => write!(f, " nodes=none")?,
+		}
+		match self.timeout {
+			Some(d) => write!(f, " time={}ms", d.as_millis())?,
+			None => write!(f, " time=none")?,
+		}
+		match self.max_depth {
+			Some(n) => write!(f, " depth={}", n)?,
+			None => write!(f, " depth=none")?,
+		}
+		write!(f, " cycles={}", if self.detect_cycles { "on" } else { "off" })
+	}
+}
+
+fn parse_count(s: &str) -> Result<usize, String> {
+	s.replace('_', "").parse().map_err(|_| format!("`{}` is not a count", s))
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+	let bad = || format!("`{}` is not a duration like 2s or 250ms", s);
+	if let Some(ms) = s.strip_suffix("ms") {
+		ms.parse().map(Duration::from_millis).map_err(|_| bad())
+	} else if let Some(secs) = s.strip_suffix('s') {
+		secs.parse().ok().and_then(|secs| Duration::try_from_secs_f64(secs).ok()).ok_or_else(bad)
+	} else {
+		Err(bad())
+	}
+}
+
+// why a normalization stopped
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Stop {
+	NormalForm,
+	StepLimit,
+	NodeLimit,
+	Timeout,
+	DepthLimit,
+	// the term reached at step `start` came back `length` steps later. That is either the term
+	// being reduced, or the one parallel outermost steps taken alongside it have reached
+	Cycle { start: usize, length: usize },
+}
+impl fmt::Display for Stop {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Stop::NormalForm => write!(f, "normal form"),
+			Stop::StepLimit => write!(f, "step limit reached"),
+			Stop::NodeLimit => write!(f, "node limit reached"),
+			Stop::Timeout => write!(f, "timed out"),
+			Stop::DepthLimit => write!(f, "depth limit reached"),
+			Stop::Cycle { start, length } => {
+				write!(f, "cycle of length {} entered at step {}", length, start)
+			},
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Reduction {
+	pub key: usize, // the last term reached
+	pub steps: usize,
+	pub stop: Stop,
+	pub memoized: bool, // jumped to a normal form remembered from an earlier reduction
+}
+
+pub(crate) struct Limiter<'a> {
+	budget: &'a Budget,
+	start: Instant,
+}
+impl<'a> Limiter<'a> {
+	pub fn new(budget: &'a Budget) -> Self {
+		Limiter {
+			budget,
+			start: Instant::now(),
+		}
+	}
+
+	// checks the step and time limits. Node limits depend on the store
+	pub fn exceeded(&self, steps: usize) -> Option<Stop> {
+		if self.budget.max_steps.is_some_and(|max| steps >= max) {
+			return Some(Stop::StepLimit);
+		}
+		if self.budget.timeout.is_some_and(|max| self.start.elapsed() >= max) {
+			return Some(Stop::Timeout);
+		}
+		None
+	}
+
+	pub fn too_many_nodes(&self, nodes: usize) -> bool {
+		self.budget.max_nodes.is_some_and(|max| nodes > max)
+	}
+
+	pub fn detect_cycles(&self) -> bool {
+		self.budget.detect_cycles
+	}
+
+	pub fn too_deep(&self, depth: usize) -> bool {
+		self.budget.max_depth.is_some_and(|max| depth > max)
+	}
+}
+
+impl TermBase {
+	pub fn normalize(&mut self, key: usize, order: TraversalOrder, budget: &Budget) -> Reduction {
+		self.reduce(key, order, budget, |_, _| ())
+	}
+
+	// rewrites `key` towards normal form within `budget`, calling `trace` with each new term.
+	// Exceeding the node limit first collects everything but the definitions and the current term,
+	// so other keys held by the caller are invalidated; `reduce_keeping` names those to keep.
+	// Hash-consing means a revisited term has the same key, so cycles are found by remembering
+	// the step at which each key was reached. Collection forgets these, as freed keys may be reused.
+	// Leftmost-outermost never comes back to SII(SII), only grows it, so other strategies also
+	// take parallel outermost steps alongside: that strategy is normalizing, so if it cycles
+	// there is no normal form to reach.
+	// Every term visited on the way to a normal form remembers it and the strategy, so reducing
+	// any of them again the same way, or with a normalizing strategy, finishes immediately.
+	pub fn reduce<F>(&mut self, key: usize, order: TraversalOrder, budget: &Budget, trace: F) -> Reduction
+	where F: FnMut(&TermBase, usize) {
+		self.reduce_keeping(key, order, budget, &[], trace)
+	}
+
+	// `reduce`, with `roots` surviving any collection along the way
+	pub fn reduce_keeping<F>(&mut self, mut key: usize, order: TraversalOrder, budget: &Budget, roots: &[usize], mut trace: F) -> Reduction
+	where F: FnMut(&TermBase, usize) {
+		let limiter = Limiter::new(budget);
+		let mut visited: FnvHashMap<usize, usize> = FnvHashMap::default();
+		let mut probe = Some(key).filter(|_| budget.detect_cycles && order != TraversalOrder::ParallelOutermost);
+		let mut probed: FnvHashMap<usize, usize> = FnvHashMap::default();
+		let mut path = vec![];
+		let mut steps = 0;
+		loop {
+			let memo = self.memoized_normal_form(key, order).filter(|&nf| nf != key);
+			if let Some(nf) = memo {
+				trace(self, nf);
+			}
+			let nf = memo.unwrap_or(key);
+			if self.normal_form(nf) {
+				for k in path {
+					self.memoize_normal_form(k, order, nf);
+				}
+				self.memoize_normal_form(key, order, nf);
+				return Reduction { key: nf, steps, stop: Stop::NormalForm, memoized: memo.is_some() };
+			}
+			path.push(key);
+			if budget.detect_cycles {
+				if let Some(&start) = visited.get(&key) {
+					let stop = Stop::Cycle { start, length: steps - start };
+					return Reduction { key, steps, stop, memoized: false };
+				}
+				visited.insert(key, steps);
+			}
+			if let Some(p) = probe {
+				if let Some(&start) = probed.get(&p) {
+					let stop = Stop::Cycle { start, length: steps - start };
+					return Reduction { key, steps, stop, memoized: false };
+				}
+				probed.insert(p, steps);
+			}
+			if let Some(stop) = limiter.exceeded(steps) {
+				return Reduction { key, steps, stop, memoized: false };
+			}
+			if limiter.too_many_nodes(self.len()) {
+				self.gc_all_but_defined_and(roots.iter().cloned().chain(Some(key)).chain(probe));
+				visited.clear();
+				probed.clear();
+				path.clear();
+				path.push(key);
+				if limiter.too_many_nodes(self.len()) {
+					return Reduction { key, steps, stop: Stop::NodeLimit, memoized: false };
+				}
+			}
+			key = self.step(key, order);
+			// a probe that reached a normal form has nothing more to say
+			probe = probe.filter(|&p| !self.normal_form(p)).map(|p| self.step(p, TraversalOrder::ParallelOutermost));
+			steps += 1;
+			trace(self, key);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn memoized_subterms_still_count_their_steps() {
+		let mut tb = TermBase::new();
+		let order = TraversalOrder::LeftmostOutermost;
+		let x = tb.parse("S(SKK)(SKK)(S(SKK)(SKK)x)").unwrap();
+		let first = tb.normalize(x, order, &Budget::unlimited());
+		assert_eq!((first.steps, first.memoized), (15, false));
+		let again = tb.normalize(x, order, &Budget::unlimited());
+		assert_eq!((again.key, again.steps, again.memoized), (first.key, 0, true));
+		let y = tb.parse("y(S(SKK)(SKK)(S(SKK)(SKK)x))").unwrap();
+		let r = tb.normalize(y, order, &Budget::unlimited());
+		assert_eq!((r.steps, r.memoized), (15, false));
+	}
+
+	#[test]
+	fn memoized_normal_forms_keep_their_strategy() {
+		let mut tb = TermBase::new();
+		let k = tb.parse("K I (S I I (S I I))").unwrap();
+		let lo = tb.normalize(k, TraversalOrder::LeftmostOutermost, &Budget::steps(100));
+		assert_eq!(lo.stop, Stop::NormalForm);
+		let li = tb.normalize(k, TraversalOrder::LeftmostInnermost, &Budget::steps(100));
+		assert_eq!((li.stop, li.memoized), (Stop::Cycle { start: 0, length: 3 }, false));
+		let po = tb.normalize(k, TraversalOrder::ParallelOutermost, &Budget::steps(100));
+		assert_eq!((po.key, po.memoized), (lo.key, true));
+	}
+
+	#[test]
+	fn cycles() {
+		let mut tb = TermBase::new();
+		let omega = tb.parse("S I I (S I I)").unwrap();
+		let cycle = Stop::Cycle { start: 0, length: 2 };
+		let po = tb.normalize(omega, TraversalOrder::ParallelOutermost, &Budget::steps(100));
+		assert_eq!((po.stop, po.key), (cycle, omega));
+		// found by the probe, as leftmost-outermost only grows the term
+		let lo = tb.normalize(omega, TraversalOrder::LeftmostOutermost, &Budget::steps(100));
+		assert_eq!(lo.stop, cycle);
+		let omega = tb.parse("[x](x x)([x](x x))").unwrap();
+		let omega = tb.lambda(omega);
+		let beta = tb.normalize(omega, TraversalOrder::LeftmostOutermost, &Budget::steps(100));
+		assert_eq!((beta.stop, beta.key), (Stop::Cycle { start: 0, length: 1 }, omega));
+		let off = Budget { detect_cycles: false, ..Budget::steps(100) };
+		assert_eq!(tb.normalize(omega, TraversalOrder::LeftmostOutermost, &off).stop, Stop::StepLimit);
+	}
+
+	#[test]
+	fn durations() {
+		assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+		assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+		for bad in ["-1s", "nans", "infs", "2"].iter() {
+			assert!(parse_duration(bad).is_err(), "{}", bad);
+		}
+	}
+}
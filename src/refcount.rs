@@ -0,0 +1,460 @@
+use fnv::FnvHashMap;
+use slab::Slab;
+
+use print::Printer;
+use reduce::{Budget, Limiter, Reduction, Stop};
+use symbol::Sym;
+use term::{Term, TermBase};
+
+// An alternative to TermBase which frees nodes as soon as nothing refers to them.
+// Every key handed out or accepted by RcTermBase is an owned reference:
+// functions taking a key consume it, functions returning a key give one back.
+// Abstractions are always eliminated naively, whatever the TermBase is set to.
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum RewriteKey {
+	NormalForm,
+	CanRewrite,
+	Unknown,
+}
+
+#[derive(Debug)]
+struct TermEntry {
+	term: Term,
+	rewrite_key: RewriteKey,
+	refcounts: usize,
+}
+
+type TSlab = Slab<TermEntry>;
+
+#[derive(Debug)]
+pub struct RcTermBase {
+	slab: TSlab,
+	slab_locator: FnvHashMap<Term, usize>, //term to slab key
+	peak: usize,
+}
+impl Default for RcTermBase {
+	fn default() -> Self {
+		RcTermBase::new()
+	}
+}
+impl RcTermBase {
+	pub fn new() -> Self {
+		RcTermBase {
+			slab: TSlab::with_capacity(128),
+			slab_locator: FnvHashMap::default(),
+			peak: 0,
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.slab.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.slab.is_empty()
+	}
+
+	// the largest number of simultaneously live nodes so far
+	pub fn peak(&self) -> usize {
+		self.peak
+	}
+
+	pub fn refcount(&self, key: usize) -> usize {
+		self.slab[key].refcounts
+	}
+
+	pub fn term(&self, key: usize) -> Term {
+		self.slab[key].term
+	}
+
+	// `key` as written, naming its variables and constants by `tb`, without copying it there
+	pub fn term_string(&self, key: usize, tb: &TermBase) -> String {
+		let mut s = String::new();
+		Printer::new_rc(self, tb, &mut s).term(key);
+		s
+	}
+
+	// consumes one reference to each child of `term`
+	fn find_and_ref_up(&mut self, term: Term) -> usize {
+		if let Some(&slab_key) = self.slab_locator.get(&term) {
+			self.slab[slab_key].refcounts += 1;
+			// the existing node already holds its children
+			match term {
+				Term::Ap(l, r) => {
+					self.ref_down(l);
+					self.ref_down(r);
+				},
+				Term::Abs(_, body) => self.ref_down(body),
+				_ => (),
+			}
+			slab_key
+		} else {
+			let slab_key = self.slab.insert(TermEntry {
+				term,
+				rewrite_key: RewriteKey::Unknown,
+				refcounts: 1,
+			});
+			self.slab_locator.insert(term, slab_key);
+			if self.slab.len() > self.peak {
+				self.peak = self.slab.len();
+			}
+			slab_key
+		}
+	}
+
+	pub fn ref_up(&mut self, key: usize) -> usize {
+		self.slab[key].refcounts += 1;
+		key
+	}
+
+	pub fn ref_down(&mut self, key: usize) {
+		let mut stack = vec![key];
+		while let Some(key) = stack.pop() {
+			self.slab[key].refcounts -= 1;
+			if self.slab[key].refcounts == 0 {
+				let entry = self.slab.remove(key);
+				self.slab_locator.remove(&entry.term);
+				match entry.term {
+					Term::Ap(l, r) => {
+						stack.push(l);
+						stack.push(r);
+					},
+					Term::Abs(_, body) => stack.push(body),
+					_ => (),
+				}
+			}
+		}
+	}
+
+	/////////////////// moving terms between stores
+
+	// copies `key` out of `tb`, returning an owned reference. Definitions are unfolded on the way, as `tb` would.
+	// Fails on bulk combinators, lambda terms and recursive definitions
+	pub fn import(&mut self, tb: &mut TermBase, key: usize) -> Result<usize, String> {
+		let mut memo = FnvHashMap::default();
+		self.import_rec(tb, key, &mut memo, &mut vec![])
+	}
+
+	fn import_rec(&mut self, tb: &mut TermBase, key: usize, memo: &mut FnvHashMap<usize, usize>, unfolding: &mut Vec<Sym>) -> Result<usize, String> {
+		if let Some(&k) = memo.get(&key) {
+			return Ok(self.ref_up(k));
+		}
+		let term = match tb.term(key) {
+			Term::Const(name) => if let Some(def) = tb.unfolding(name) {
+				if unfolding.contains(&name) {
+					return Err(format!("the rc store cannot unfold recursive `{}`", tb.name(name)));
+				}
+				unfolding.push(name);
+				let k = self.import_rec(tb, def, memo, unfolding);
+				unfolding.pop();
+				let k = k?;
+				memo.insert(key, k);
+				return Ok(k);
+			} else {
+				Term::Const(name)
+			},
+			Term::Ap(l, r) => {
+				let l = self.import_rec(tb, l, memo, unfolding)?;
+				match self.import_rec(tb, r, memo, unfolding) {
+					Ok(r) => Term::Ap(l, r),
+					Err(e) => {
+						self.ref_down(l);
+						return Err(e);
+					},
+				}
+			},
+			Term::Abs(v, body) => Term::Abs(v, self.import_rec(tb, body, memo, unfolding)?),
+			Term::Bulk(_, _) => {
+				return Err(format!("the rc store cannot rewrite bulk combinators like {}", tb.term_string(key)));
+			},
+			Term::Comb(id) => {
+				return Err(format!("the rc store cannot rewrite combinators declared by rules, like {}", tb.rule_name(id)));
+			},
+			Term::Lam(_) |
+			Term::Idx(_) => return Err("the rc store cannot beta reduce".to_owned()),
+			t => t,
+		};
+		let k = self.find_and_ref_up(term);
+		memo.insert(key, k);
+		Ok(k)
+	}
+
+	// copies `key` into `tb`. `key` remains owned by the caller
+	pub fn export(&self, key: usize, tb: &mut TermBase) -> usize {
+		let mut memo = FnvHashMap::default();
+		self.export_rec(key, tb, &mut memo)
+	}
+
+	fn export_rec(&self, key: usize, tb: &mut TermBase, memo: &mut FnvHashMap<usize, usize>) -> usize {
+		if let Some(&k) = memo.get(&key) {
+			return k;
+		}
+		let k = match self.slab[key].term {
+			Term::Ap(l, r) => {
+				let l = self.export_rec(l, tb, memo);
+				let r = self.export_rec(r, tb, memo);
+				tb.ap(l, r)
+			},
+			Term::Abs(v, body) => {
+				let body = self.export_rec(body, tb, memo);
+				tb.find_and_ref_up(Term::Abs(v, body))
+			},
+			Term::Lam(body) => {
+				let body = self.export_rec(body, tb, memo);
+				tb.find_and_ref_up(Term::Lam(body))
+			},
+			Term::I => tb.i(),
+			Term::K => tb.k(),
+			Term::S => tb.s(),
+			Term::B => tb.b(),
+			Term::C => tb.c(),
+			Term::W => tb.w(),
+			Term::X => tb.x(),
+			t @ Term::Bulk(_, _) |
+			t @ Term::Comb(_) |
+			t @ Term::Idx(_) |
+			t @ Term::Const(_) |
+			t @ Term::Var(_) => tb.find_and_ref_up(t),
+		};
+		memo.insert(key, k);
+		k
+	}
+
+	/////////////////// rewriting
+
+	fn is(&self, key: usize, term: Term) -> bool {
+		self.slab[key].term == term
+	}
+
+	fn root_can_rewrite(&self, key: usize) -> bool {
+		let t = self.slab[key].term;
+		if let Term::Abs(_, a) = t {
+			match self.slab[a].term {
+				Term::Abs(_, _) => (),
+				_ => return true,
+			}
+		}
+		if let Term::Ap(l, _) = t {
+			if self.is(l, Term::I) || self.is(l, Term::X) {
+				return true
+			}
+			if let Term::Ap(ll, _) = self.slab[l].term {
+				if self.is(ll, Term::K) || self.is(ll, Term::W) {
+					return true
+				}
+				if let Term::Ap(lll, _) = self.slab[ll].term {
+					if self.is(lll, Term::S) || self.is(lll, Term::B) || self.is(lll, Term::C) {
+						return true
+					}
+				}
+			}
+		}
+		false
+	}
+
+	// consumes `key`. Only called when root_can_rewrite holds
+	fn root_rewrite(&mut self, key: usize) -> usize {
+		let t = self.slab[key].term;
+		if let Term::Abs(v, a) = t {
+			let q = self.slab[a].term;
+			let new_key = if let Term::Var(x) = q {
+				if v == x {
+					self.find_and_ref_up(Term::I)
+				} else {
+					let k = self.find_and_ref_up(Term::K);
+					self.ref_up(a);
+					self.find_and_ref_up(Term::Ap(k, a))
+				}
+			} else if let Term::Ap(al, ar) = q {
+				let s = self.find_and_ref_up(Term::S);
+				self.ref_up(al);
+				self.ref_up(ar);
+				let new_left = self.find_and_ref_up(Term::Abs(v, al));
+				let new_right = self.find_and_ref_up(Term::Abs(v, ar));
+				let inner = self.find_and_ref_up(Term::Ap(s, new_left));
+				self.find_and_ref_up(Term::Ap(inner, new_right))
+			} else {
+				let k = self.find_and_ref_up(Term::K);
+				self.ref_up(a);
+				self.find_and_ref_up(Term::Ap(k, a))
+			};
+			self.ref_down(key); // delete [v]a
+			return new_key;
+		}
+		if let Term::Ap(l, r) = t {
+			// I
+			if self.is(l, Term::I) {
+				self.ref_up(r);     // keep   x
+				self.ref_down(key); // delete Ix
+				return r;
+			}
+			// X
+			if self.is(l, Term::X) {
+				self.ref_up(r);
+				let s = self.find_and_ref_up(Term::S);
+				let k = self.find_and_ref_up(Term::K);
+				let left = self.find_and_ref_up(Term::Ap(r, s));
+				let whole = self.find_and_ref_up(Term::Ap(left, k));
+				self.ref_down(key); // delete Xf
+				return whole;
+			}
+			if let Term::Ap(ll, lr) = self.slab[l].term {
+				// K
+				if self.is(ll, Term::K) {
+					self.ref_up(lr);    // keep   x
+					self.ref_down(key); // delete Kxy
+					return lr;
+				}
+				// W
+				if self.is(ll, Term::W) {
+					self.ref_up(lr);
+					self.ref_up(r);
+					self.ref_up(r);
+					let left = self.find_and_ref_up(Term::Ap(lr, r));
+					let whole = self.find_and_ref_up(Term::Ap(left, r));
+					self.ref_down(key); // delete Wxy
+					return whole;
+				}
+				// S
+				if let Term::Ap(lll, llr) = self.slab[ll].term {
+					if self.is(lll, Term::S) {
+						self.ref_up(llr);
+						self.ref_up(lr);
+						self.ref_up(r);
+						self.ref_up(r);
+						let left = self.find_and_ref_up(Term::Ap(llr, r));
+						let right = self.find_and_ref_up(Term::Ap(lr, r));
+						let whole = self.find_and_ref_up(Term::Ap(left, right));
+						self.ref_down(key); // delete Sxyz
+						return whole;
+					}
+					if self.is(lll, Term::B) {
+						self.ref_up(llr);
+						self.ref_up(lr);
+						self.ref_up(r);
+						let right = self.find_and_ref_up(Term::Ap(lr, r));
+						let whole = self.find_and_ref_up(Term::Ap(llr, right));
+						self.ref_down(key); // delete Bxyz
+						return whole;
+					}
+					if self.is(lll, Term::C) {
+						self.ref_up(llr);
+						self.ref_up(lr);
+						self.ref_up(r);
+						let left = self.find_and_ref_up(Term::Ap(llr, r));
+						let whole = self.find_and_ref_up(Term::Ap(left, lr));
+						self.ref_down(key); // delete Cxyz
+						return whole;
+					}
+				}
+			}
+		}
+		panic!("root_rewrite without a redex");
+	}
+
+	pub fn normal_form(&mut self, key: usize) -> bool {
+		self.assure_rewrite_key_known(key);
+		self.slab[key].rewrite_key == RewriteKey::NormalForm
+	}
+
+	fn assure_rewrite_key_known(&mut self, key: usize) {
+		if self.slab[key].rewrite_key == RewriteKey::Unknown {
+			if match self.slab[key].term {
+				Term::Ap(l, r) => {
+					self.normal_form(l)
+					&& self.normal_form(r)
+					&& !self.root_can_rewrite(key)
+				}
+				Term::Abs(_, term) => {
+					!self.root_can_rewrite(key)
+					&& self.normal_form(term)
+				}
+				_ => true,
+			} {
+				self.slab[key].rewrite_key = RewriteKey::NormalForm;
+			} else {
+				self.slab[key].rewrite_key = RewriteKey::CanRewrite;
+			}
+		}
+	}
+
+	// consumes `key`, returning its successor
+	pub fn outermost_leftmost(&mut self, key: usize) -> usize {
+		if self.normal_form(key) {
+			return key;
+		}
+		if self.root_can_rewrite(key) {
+			return self.root_rewrite(key);
+		}
+		let new_term = match self.slab[key].term {
+			Term::Ap(l, r) => {
+				self.ref_up(l);
+				self.ref_up(r);
+				if self.normal_form(l) {
+					let x = self.outermost_leftmost(r);
+					Term::Ap(l, x)
+				} else {
+					let x = self.outermost_leftmost(l);
+					Term::Ap(x, r)
+				}
+			},
+			Term::Abs(v, term) => {
+				self.ref_up(term);
+				Term::Abs(v, self.outermost_leftmost(term))
+			},
+			_ => panic!("normal form didnt catch"),
+		};
+		let new_key = self.find_and_ref_up(new_term);
+		self.ref_down(key);
+		new_key
+	}
+
+	// consumes `key`, returning an owned reference to the last term reached.
+	// `trace` sees each new term. Keys are reused as soon as they are freed,
+	// so `budget.detect_cycles` is not supported here
+	pub fn reduce<F>(&mut self, mut key: usize, budget: &Budget, mut trace: F) -> Reduction
+	where F: FnMut(&RcTermBase, usize) {
+		let limiter = Limiter::new(budget);
+		let mut steps = 0;
+		loop {
+			if self.normal_form(key) {
+				return Reduction { key, steps, stop: Stop::NormalForm, memoized: false };
+			}
+			if let Some(stop) = limiter.exceeded(steps) {
+				return Reduction { key, steps, stop, memoized: false };
+			}
+			if limiter.too_many_nodes(self.len()) {
+				return Reduction { key, steps, stop: Stop::NodeLimit, memoized: false };
+			}
+			key = self.outermost_leftmost(key);
+			steps += 1;
+			trace(self, key);
+		}
+	}
+
+	pub fn normalize(&mut self, key: usize, budget: &Budget) -> Reduction {
+		self.reduce(key, budget, |_, _| ())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn nodes_are_freed_as_they_die() {
+		let mut tb = TermBase::new();
+		let mut rc = RcTermBase::new();
+		// each S step shares its last argument between two applications
+		let k = tb.parse("S(SKK)(SKK)(S(SKK)(SKK)x)").unwrap();
+		let k = rc.import(&mut tb, k).unwrap();
+		let r = rc.normalize(k, &Budget::steps(100));
+		assert_eq!((r.stop, r.steps), (Stop::NormalForm, 15));
+		let nf = rc.export(r.key, &mut tb);
+		assert_eq!(tb.term_string(nf), "x x(x x)");
+		rc.ref_down(r.key);
+		assert_eq!(rc.len(), 0);
+		assert!(rc.peak() <= 16, "peak {}", rc.peak());
+	}
+}
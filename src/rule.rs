@@ -0,0 +1,141 @@
+use fnv::FnvHashMap;
+
+use parser::{ParseError, Parser};
+use symbol::Sym;
+use term::{Term, TermBase};
+
+// A combinator declared by `rule NAME x1 .. xn = rhs`, where NAME x1 .. xn contracts to rhs.
+// Its pattern variables are distinct, and are the only variables free in rhs
+#[derive(Clone, Debug)]
+pub(crate) struct Rule {
+	pub name: Sym,
+	pub params: Vec<Sym>,
+	pub rhs: usize, // compiled, so free of binders
+}
+
+impl TermBase {
+	// parses and declares `NAME x1 .. xn = rhs`, replacing any earlier rule for NAME
+	pub fn parse_rule(&mut self, src: &str) -> Result<Sym, ParseError> {
+		let (name, params, rhs) = Parser::parse_rule(self, src)?;
+		let rule = Rule { name, params, rhs };
+		match self.rule(self.name(name)) {
+			Some(id) => self.rules[id as usize] = rule,
+			None => self.rules.push(rule),
+		}
+		// a failed declaration may have left nodes of this combinator, taken for normal forms
+		self.forget_rewrites();
+		Ok(name)
+	}
+
+	// the combinator declared as `name`
+	pub(crate) fn rule(&self, name: &str) -> Option<u32> {
+		let name = self.symbols.get(name)?;
+		self.rules.iter().position(|rule| rule.name == name).map(|id| id as u32)
+	}
+
+	pub(crate) fn rule_name(&self, id: u32) -> &str {
+		self.name(self.rules[id as usize].name)
+	}
+
+	// `NAME x1 .. xn` as written when declared, and its right-hand side
+	pub fn rules(&self) -> impl Iterator<Item=(String, usize)> + '_ {
+		self.rules.iter().map(move |rule| {
+			let mut pattern = self.name(rule.name).to_owned();
+			for &v in rule.params.iter() {
+				pattern.push(' ');
+				pattern.push_str(self.name(v));
+			}
+			(pattern, rule.rhs)
+		})
+	}
+
+	// the combinator and arguments of `key` if it is a saturated declared combinator
+	pub(crate) fn rule_redex(&self, key: usize) -> Option<(u32, Vec<usize>)> {
+		let mut args = vec![];
+		let mut head = key;
+		while let Term::Ap(l, r) = self.term(head) {
+			args.push(r);
+			head = l;
+		}
+		match self.term(head) {
+			Term::Comb(id) if args.len() == self.rules[id as usize].params.len() => {
+				args.reverse();
+				Some((id, args))
+			},
+			_ => None,
+		}
+	}
+
+	// the right-hand side of rule `id` with its pattern variables replaced by `args`
+	pub(crate) fn rule_rewrite(&mut self, id: u32, args: &[usize]) -> usize {
+		let Rule { params, rhs, .. } = self.rules[id as usize].clone();
+		let mut memo: FnvHashMap<usize, usize> = FnvHashMap::default();
+		for (v, &arg) in params.into_iter().zip(args) {
+			let var = self.find_and_ref_up(Term::Var(v));
+			memo.insert(var, arg);
+		}
+		self.instantiate(rhs, &mut memo)
+	}
+
+	fn instantiate(&mut self, key: usize, memo: &mut FnvHashMap<usize, usize>) -> usize {
+		if let Some(&k) = memo.get(&key) {
+			return k;
+		}
+		let k = match self.term(key) {
+			Term::Ap(l, r) => {
+				let l = self.instantiate(l, memo);
+				let r = self.instantiate(r, memo);
+				self.ap(l, r)
+			},
+			_ => key,
+		};
+		memo.insert(key, k);
+		k
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use reduce::{Budget, Stop};
+	use traverse::TraversalOrder;
+
+	fn normal_form(tb: &mut TermBase, src: &str) -> String {
+		let k = tb.parse(src).unwrap();
+		let r = tb.normalize(k, TraversalOrder::LeftmostOutermost, &Budget::steps(100));
+		assert_eq!(r.stop, Stop::NormalForm);
+		tb.term_string(r.key)
+	}
+
+	#[test]
+	fn patterns_are_left_linear() {
+		let e = TermBase::new().parse_rule("Q x x = x").unwrap_err();
+		assert_eq!((e.pos, e.expected), (4, "a new variable"));
+	}
+
+	#[test]
+	fn rhs_variables_are_bound_by_the_pattern() {
+		let e = TermBase::new().parse_rule("R x = y").unwrap_err();
+		assert_eq!((e.pos, e.expected), (6, "a pattern variable"));
+	}
+
+	#[test]
+	fn rules_contract() {
+		let mut tb = TermBase::new();
+		tb.parse_rule("W x y = x y y").unwrap();
+		assert_eq!(normal_form(&mut tb, "W f a"), "f a a");
+		tb.parse_rule("Q x y = y x").unwrap();
+		assert_eq!(normal_form(&mut tb, "Q a b c"), "b a c");
+		assert_eq!(normal_form(&mut tb, "Q a"), "Q a");
+	}
+
+	#[test]
+	fn redeclaring_replaces_the_rule() {
+		let mut tb = TermBase::new();
+		tb.parse_rule("W x y = x y y").unwrap();
+		assert_eq!(normal_form(&mut tb, "W f a"), "f a a");
+		tb.parse_rule("W x y = y x").unwrap();
+		assert_eq!(tb.rules().count(), 1);
+		assert_eq!(normal_form(&mut tb, "W f a"), "a f");
+	}
+}
@@ -0,0 +1,82 @@
+use fnv::FnvHashMap;
+
+use std::fmt;
+
+// An interned identifier. Compare and hash these instead of the names they stand for.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Sym(u32);
+impl fmt::Debug for Sym {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "#{}", self.0)
+	}
+}
+
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+	names: Vec<String>,
+	ids: FnvHashMap<String, Sym>,
+}
+impl SymbolTable {
+	pub fn new() -> Self {
+		SymbolTable::default()
+	}
+
+	pub fn intern(&mut self, name: &str) -> Sym {
+		if let Some(&sym) = self.ids.get(name) {
+			return sym;
+		}
+		let sym = Sym(self.names.len() as u32);
+		self.names.push(name.to_owned());
+		self.ids.insert(name.to_owned(), sym);
+		sym
+	}
+
+	pub fn get(&self, name: &str) -> Option<Sym> {
+		self.ids.get(name).cloned()
+	}
+
+	pub fn name(&self, sym: Sym) -> &str {
+		&self.names[sym.0 as usize]
+	}
+}
+
+// identifiers are either a lowercase letter or `_` followed by letters, digits, `_` and `'`,
+// or a single uppercase letter followed by digits and `'` (so `SKx` is three tokens)
+pub fn is_lower_start(c: char) -> bool {
+	c.is_ascii_lowercase() || c == '_'
+}
+
+pub fn is_lower_continue(c: char) -> bool {
+	c.is_ascii_alphanumeric() || c == '_' || c == '\''
+}
+
+pub fn is_upper_continue(c: char) -> bool {
+	c.is_ascii_digit() || c == '\''
+}
+
+// the length of the identifier at the start of `s`, if any
+pub fn ident_len(s: &str) -> Option<usize> {
+	let mut chars = s.chars();
+	let first = chars.next()?;
+	let rest = if is_lower_start(first) {
+		chars.take_while(|&c| is_lower_continue(c)).count()
+	} else if first.is_ascii_uppercase() {
+		chars.take_while(|&c| is_upper_continue(c)).count()
+	} else {
+		return None;
+	};
+	Some(1 + rest) // all ASCII
+}
+
+pub fn is_ident(s: &str) -> bool {
+	ident_len(s) == Some(s.len())
+}
+
+// candidate names for invented variables: x, y, z, w, u, v, x1, y1, ..
+pub fn fresh_names() -> impl Iterator<Item = String> {
+	const NAMES: [&str; 6] = ["x", "y", "z", "w", "u", "v"];
+	(0..).map(|i| match i / NAMES.len() {
+		0 => NAMES[i].to_owned(),
+		n => format!("{}{}", NAMES[i % NAMES.len()], n),
+	})
+}
@@ -0,0 +1,868 @@
+use fnv::{FnvHashMap, FnvHashSet};
+use slab::Slab;
+
+use abstraction::Abstraction;
+use base::Base;
+use bulk::Bulk;
+use parser::{ParseError, Parser};
+use rule::Rule;
+use symbol::{self, Sym, SymbolTable};
+use traverse::TraversalOrder;
+use types::Type;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum RewriteKey {
+	NormalForm,
+	CanRewrite,
+	Unknown,
+}
+
+#[derive(Debug)]
+struct TermEntry {
+	term: Term,
+	rewrite_key: RewriteKey,
+	successor: Option<(TraversalOrder, usize)>,   // memoized result of `step`
+	normal_form: Option<(TraversalOrder, usize)>, // memoized result of `reduce`
+	principal: Option<Type>,                      // memoized type of a closed term
+}
+
+
+#[derive(Clone, PartialEq, Eq, Hash, Copy, Debug)]
+pub enum Term {
+	I, K, S, B, C,
+	W, // W x y = x y y
+	X, // iota, X f = f S K
+	Bulk(Bulk, u32),
+	Comb(u32), // a combinator declared by a rule
+	Var(Sym),
+	Ap(usize, usize),
+	Abs(Sym, usize),
+	Lam(usize), // a de Bruijn abstraction, binding Idx(0) in its body
+	Idx(u32),
+	Const(Sym), // a defined name, unfolded when rewritten
+}
+impl Term {
+	pub fn atomic(&self) -> bool {
+		match self {
+			Term::I |
+			Term::K |
+			Term::S |
+			Term::B |
+			Term::C |
+			Term::W |
+			Term::X |
+			Term::Bulk(_, _) |
+			Term::Comb(_) |
+			Term::Idx(_) |
+			Term::Const(_) |
+			Term::Var(_) => true,
+			Term::Ap(_,_) |
+			Term::Abs(_,_) |
+			Term::Lam(_) => false,
+		}
+	}
+}
+
+// What a defined name unfolds to
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Unfold {
+	Written,  // the term as parsed
+	Compiled, // with its abstractions compiled to combinators
+	Lambda,   // in de Bruijn form, for beta reduction
+}
+
+type TSlab = Slab<TermEntry>;
+
+#[derive(Debug)]
+pub struct TermBase {
+	slab: TSlab,
+	slab_locator: FnvHashMap<Term, usize>, //term to slab key
+	pub(crate) symbols: SymbolTable,
+	defined: FnvHashMap<Sym, usize>,
+	unfold: Unfold,
+	unfolded: FnvHashMap<Sym, usize>, // definitions in the current Unfold form
+	pub(crate) collections: usize, // garbage collections so far
+	pub(crate) rules: Vec<Rule>, // indexed by the id of Term::Comb
+	i_key: usize,
+	k_key: usize,
+	s_key: usize,
+	b_key: usize,
+	c_key: usize,
+	w_key: usize,
+	x_key: usize,
+	base: Base,
+	abstraction: Abstraction,
+}
+impl Default for TermBase {
+	fn default() -> Self {
+		TermBase::new()
+	}
+}
+impl TermBase {
+	pub fn new() -> Self {
+		let mut t = TermBase {
+			slab: TSlab::with_capacity(128),
+			slab_locator: FnvHashMap::default(),
+			symbols: SymbolTable::new(),
+			defined: FnvHashMap::default(),
+			unfold: Unfold::Written,
+			unfolded: FnvHashMap::default(),
+			collections: 0,
+			rules: vec![],
+			i_key: 0,
+			k_key: 0,
+			s_key: 0,
+			b_key: 0,
+			c_key: 0,
+			w_key: 0,
+			x_key: 0,
+			base: Base::SKI,
+			abstraction: Abstraction::KEta,
+		};
+		t.i_key = t.find_and_ref_up(Term::I);
+		t.k_key = t.find_and_ref_up(Term::K);
+		t.s_key = t.find_and_ref_up(Term::S);
+		t.b_key = t.find_and_ref_up(Term::B);
+		t.c_key = t.find_and_ref_up(Term::C);
+		t.w_key = t.find_and_ref_up(Term::W);
+		t.x_key = t.find_and_ref_up(Term::X);
+		t
+	}
+
+	/////////////////// constructors
+
+	pub fn i(&self) -> usize {
+		self.i_key
+	}
+
+	pub fn k(&self) -> usize {
+		self.k_key
+	}
+
+	pub fn s(&self) -> usize {
+		self.s_key
+	}
+
+	pub fn b(&self) -> usize {
+		self.b_key
+	}
+
+	pub fn c(&self) -> usize {
+		self.c_key
+	}
+
+	pub fn w(&self) -> usize {
+		self.w_key
+	}
+
+	pub fn x(&self) -> usize {
+		self.x_key
+	}
+
+	pub fn var(&mut self, name: &str) -> usize {
+		let v = self.symbols.intern(name);
+		self.find_and_ref_up(Term::Var(v))
+	}
+
+	pub fn ap(&mut self, l: usize, r: usize) -> usize {
+		self.find_and_ref_up(Term::Ap(l, r))
+	}
+
+	pub fn abs(&mut self, name: &str, body: usize) -> usize {
+		let v = self.symbols.intern(name);
+		self.find_and_ref_up(Term::Abs(v, body))
+	}
+
+	pub fn term(&self, key: usize) -> Term {
+		self.slab[key].term
+	}
+
+	pub fn symbols(&self) -> &SymbolTable {
+		&self.symbols
+	}
+
+	pub fn name(&self, sym: Sym) -> &str {
+		self.symbols.name(sym)
+	}
+
+	// names which can be neither bound nor defined
+	pub fn is_keyword(&self, name: &str) -> bool {
+		self.primitive(name).is_some()
+	}
+
+	// the primitive combinator of the current base or declared by a rule called `name`
+	pub fn primitive(&self, name: &str) -> Option<Term> {
+		self.base_primitive(name).or_else(|| self.rule(name).map(Term::Comb))
+	}
+
+	fn base_primitive(&self, name: &str) -> Option<Term> {
+		let t = match name {
+			"S" => Term::S,
+			"K" => Term::K,
+			"I" => Term::I,
+			"B" => Term::B,
+			"C" => Term::C,
+			"W" => Term::W,
+			"X" => Term::X,
+			_ if self.base.has_bulk() => return Bulk::parse(name).map(|(kind, n)| Term::Bulk(kind, n)),
+			_ => return None,
+		};
+		if self.base.primitives().contains(&name) { Some(t) } else { None }
+	}
+
+	pub fn base(&self) -> Base {
+		self.base
+	}
+
+	// fails if a definition's name would become a primitive
+	pub fn set_base(&mut self, base: Base) -> Result<(), String> {
+		if !base.has_bc() && self.abstraction == Abstraction::Kiselyov {
+			return Err(format!("kiselyov abstraction needs B and C, which {} lacks", base));
+		}
+		let old = self.base;
+		self.base = base;
+		if let Some(name) = self.defined.keys().map(|&n| self.symbols.name(n)).find(|n| self.is_keyword(n)) {
+			let e = format!("`{}` is defined, so it cannot become a primitive", name);
+			self.base = old;
+			return Err(e);
+		}
+		if let Some(name) = self.rules.iter().map(|rule| self.symbols.name(rule.name)).find(|n| self.base_primitive(n).is_some()) {
+			let e = format!("`{}` is declared by a rule, so it cannot become a primitive", name);
+			self.base = old;
+			return Err(e);
+		}
+		self.forget_rewrites();
+		Ok(())
+	}
+
+	pub fn abstraction(&self) -> Abstraction {
+		self.abstraction
+	}
+
+	pub fn set_abstraction(&mut self, abstraction: Abstraction) -> Result<(), String> {
+		if abstraction == Abstraction::Kiselyov && !self.base.has_bc() {
+			return Err(format!("kiselyov abstraction needs B and C, which {} lacks", self.base));
+		}
+		self.abstraction = abstraction;
+		self.forget_rewrites();
+		Ok(())
+	}
+
+	pub fn unfold(&self) -> Unfold {
+		self.unfold
+	}
+
+	pub fn set_unfold(&mut self, unfold: Unfold) {
+		if unfold != self.unfold {
+			self.unfold = unfold;
+			self.forget_rewrites();
+		}
+	}
+
+	// drops everything learned about rewriting, after the rules or definitions have changed
+	pub(crate) fn forget_rewrites(&mut self) {
+		self.unfolded.clear();
+		for (_, entry) in self.slab.iter_mut() {
+			entry.rewrite_key = RewriteKey::Unknown;
+			entry.successor = None;
+			entry.normal_form = None;
+			entry.principal = None;
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.slab.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.slab.is_empty()
+	}
+
+	// the number of atoms and binders in `key` written out as a tree, ignoring sharing
+	pub fn size(&self, key: usize) -> usize {
+		let mut sizes: FnvHashMap<usize, usize> = FnvHashMap::default();
+		let mut stack = vec![(key, false)];
+		while let Some((k, expanded)) = stack.pop() {
+			if sizes.contains_key(&k) {
+				continue;
+			}
+			match self.term(k) {
+				Term::Ap(l, r) if expanded => {
+					let n = sizes[&l].saturating_add(sizes[&r]);
+					sizes.insert(k, n);
+				},
+				Term::Abs(_, body) |
+				Term::Lam(body) if expanded => {
+					let n = sizes[&body].saturating_add(1);
+					sizes.insert(k, n);
+				},
+				Term::Ap(l, r) => stack.extend_from_slice(&[(k, true), (l, false), (r, false)]),
+				Term::Abs(_, body) |
+				Term::Lam(body) => stack.extend_from_slice(&[(k, true), (body, false)]),
+				_ => {
+					sizes.insert(k, 1);
+				},
+			}
+		}
+		sizes[&key]
+	}
+
+	pub(crate) fn find_and_ref_up(&mut self, term: Term) -> usize {
+		if let Some(&slab_key) = self.slab_locator.get(&term) {
+			debug_assert_eq!(self.slab[slab_key].term, term);
+			slab_key
+		} else {
+			let slab_key = self.slab.insert(TermEntry {
+				term,
+				rewrite_key: RewriteKey::Unknown,
+				successor: None,
+				normal_form: None,
+				principal: None,
+			});
+			self.slab_locator.insert(term, slab_key);
+			slab_key
+		}
+	}
+
+	pub(crate) fn principal(&self, key: usize) -> Option<&Type> {
+		self.slab[key].principal.as_ref()
+	}
+
+	pub(crate) fn set_principal(&mut self, key: usize, t: Type) {
+		self.slab[key].principal = Some(t);
+	}
+
+	// every slab entry is indexed by its own term, and nothing else is indexed
+	pub fn check_locator(&self) -> Result<(), String> {
+		for (key, entry) in self.slab.iter() {
+			match self.slab_locator.get(&entry.term) {
+				Some(&k) if k == key => (),
+				Some(&k) => return Err(format!("{:?} at {} is indexed as {}", entry.term, key, k)),
+				None => return Err(format!("{:?} at {} is not indexed", entry.term, key)),
+			}
+		}
+		for (term, &key) in self.slab_locator.iter() {
+			match self.slab.get(key) {
+				Some(entry) if entry.term == *term => (),
+				Some(entry) => return Err(format!("{:?} is indexed to {} holding {:?}", term, key, entry.term)),
+				None => return Err(format!("{:?} is indexed to vacant {}", term, key)),
+			}
+		}
+		Ok(())
+	}
+
+	/////////////////// parsing and definitions
+
+	pub fn parse(&mut self, src: &str) -> Result<usize, ParseError> {
+		Parser::parse(self, src)
+	}
+
+	// parses `name = term` and defines it. The term may refer to `name` itself
+	pub fn parse_definition(&mut self, src: &str) -> Result<(Sym, usize), ParseError> {
+		let (name, key) = Parser::parse_definition(self, src)?;
+		self.redefine(name, key);
+		Ok((name, key))
+	}
+
+	pub fn define(&mut self, name: &str, key: usize) -> Result<(), String> {
+		if !symbol::is_ident(name) || self.is_keyword(name) {
+			return Err(format!("`{}` cannot be defined", name));
+		}
+		let name = self.symbols.intern(name);
+		self.redefine(name, key);
+		Ok(())
+	}
+
+	// terms refer to definitions by name, so a redefinition changes how they rewrite
+	fn redefine(&mut self, name: Sym, key: usize) {
+		if self.defined.insert(name, key).is_some() {
+			self.forget_rewrites();
+		}
+	}
+
+	// the term `Const(name)` unfolds to
+	pub(crate) fn unfolding(&mut self, name: Sym) -> Option<usize> {
+		if let Some(&key) = self.unfolded.get(&name) {
+			return Some(key);
+		}
+		let key = *self.defined.get(&name)?;
+		let key = match self.unfold {
+			Unfold::Written => key,
+			Unfold::Compiled => self.compile(key),
+			Unfold::Lambda => self.lambda(key),
+		};
+		self.unfolded.insert(name, key);
+		Some(key)
+	}
+
+	pub fn definition(&self, name: &str) -> Option<usize> {
+		let name = self.symbols.get(name)?;
+		self.defined.get(&name).cloned()
+	}
+
+	pub fn definitions(&self) -> impl Iterator<Item=(&str, usize)> + '_ {
+		self.defined.iter().map(move |(&name, &key)| (self.symbols.name(name), key))
+	}
+
+	/////////////////// rewriting
+
+	pub(crate) fn root_can_rewrite(&mut self, key: usize) -> bool {
+		let t = self.slab[key].term;
+		if let Term::Const(name) = t {
+			return self.defined.contains_key(&name);
+		}
+		if let Term::Abs(v, a) = t {
+			return self.abs_can_rewrite(v, a);
+		}
+		if self.bulk_redex(key).is_some() || self.rule_redex(key).is_some() {
+			return true;
+		}
+		if let Term::Ap(l, _) = t {
+			if l == self.i_key || l == self.x_key {
+				return true
+			}
+			if let Term::Lam(_) = self.slab[l].term {
+				return true
+			}
+			if let Term::Ap(ll, _) = self.slab[l].term {
+				if ll == self.k_key || ll == self.w_key {
+					return true
+				}
+				if let Term::Ap(lll, _) = self.slab[ll].term {
+					if lll == self.s_key || lll == self.b_key || lll == self.c_key {
+						return true
+					}
+				}
+			}
+		}
+		false
+	}
+
+	fn root_rewrite(&mut self, key: usize) -> usize {
+		let t = self.slab[key].term;
+		if let Term::Const(name) = t {
+			if let Some(unfolded) = self.unfolding(name) {
+				return unfolded;
+			}
+		}
+		if let Term::Abs(v, a) = t {
+			if self.abs_can_rewrite(v, a) {
+				let base = self.base;
+				let once = self.abs_rewrite(v, a);
+				return self.translate(once, base);
+			}
+		}
+		if let Some((kind, args)) = self.bulk_redex(key) {
+			return self.bulk_rewrite(kind, &args);
+		}
+		if let Some((id, args)) = self.rule_redex(key) {
+			return self.rule_rewrite(id, &args);
+		}
+		if let Term::Ap(l, r) = t {
+			if l == self.i_key {
+				return r;
+			}
+			if l == self.x_key {
+				let (s, k) = (self.s_key, self.k_key);
+				let rs = self.find_and_ref_up(Term::Ap(r, s));
+				return self.find_and_ref_up(Term::Ap(rs, k));
+			}
+			if let Term::Lam(body) = self.slab[l].term {
+				return self.beta(body, r);
+			}
+			if let Term::Ap(ll, lr) = self.slab[l].term {
+				if ll == self.k_key {
+					return lr;
+				}
+				if ll == self.w_key {
+					let left = self.find_and_ref_up(Term::Ap(lr, r));
+					return self.find_and_ref_up(Term::Ap(left, r));
+				}
+				if let Term::Ap(lll, llr) = self.slab[ll].term {
+					if lll == self.s_key {
+						let left = self.find_and_ref_up(Term::Ap(llr, r));
+						let right = self.find_and_ref_up(Term::Ap(lr, r));
+						return self.find_and_ref_up(Term::Ap(left, right));
+					}
+					if lll == self.b_key {
+						let right = self.find_and_ref_up(Term::Ap(lr, r));
+						return self.find_and_ref_up(Term::Ap(llr, right));
+					}
+					if lll == self.c_key {
+						let left = self.find_and_ref_up(Term::Ap(llr, r));
+						return self.find_and_ref_up(Term::Ap(left, lr));
+					}
+				}
+			}
+		}
+		key //no change!
+	}
+
+	pub fn normal_form(&mut self, key: usize) -> bool {
+		self.assure_rewrite_key_known(key);
+		self.slab[key].rewrite_key == RewriteKey::NormalForm
+	}
+
+	fn assure_rewrite_key_known(&mut self, key: usize) {
+		if self.slab[key].rewrite_key == RewriteKey::Unknown {
+			if match self.slab[key].term {
+				Term::I |
+				Term::K |
+				Term::S |
+				Term::B |
+				Term::C |
+				Term::W |
+				Term::X |
+				Term::Bulk(_, _) |
+				Term::Comb(_) |
+				Term::Idx(_) |
+				Term::Var(_) => true,
+				Term::Const(_) => !self.root_can_rewrite(key),
+				Term::Ap(l, r) => {
+					self.normal_form(l)
+					&& self.normal_form(r)
+					&& !self.root_can_rewrite(key)
+				}
+				Term::Abs(_, term) => {
+					!self.root_can_rewrite(key)
+					&& self.normal_form(term)
+				}
+				Term::Lam(term) => self.normal_form(term),
+			} {
+				self.slab[key].rewrite_key = RewriteKey::NormalForm;
+			} else {
+				self.slab[key].rewrite_key = RewriteKey::CanRewrite;
+			}
+		}
+	}
+
+	pub fn outermost_leftmost(&mut self, key: usize) -> usize {
+		if self.normal_form(key) {
+			return key;
+		}
+		let t = self.slab[key].term;
+		// a redex may rewrite to itself, as in (λx.xx)(λx.xx)
+		if self.root_can_rewrite(key) {
+			return self.root_rewrite(key);
+		}
+		match t {
+			Term::I |
+			Term::K |
+			Term::S |
+			Term::B |
+			Term::C |
+			Term::W |
+			Term::X |
+			Term::Bulk(_, _) |
+			Term::Comb(_) |
+			Term::Idx(_) |
+			Term::Const(_) |
+			Term::Var(_) => panic!("normal form didnt catch"),
+			Term::Ap(l, r) => {
+				// a subterm may step to itself, so test for normal form rather than change
+				if !self.normal_form(l) {
+					let x = self.step(l, TraversalOrder::LeftmostOutermost);
+					return self.find_and_ref_up(Term::Ap(x, r));
+				}
+				let x = self.step(r, TraversalOrder::LeftmostOutermost);
+				self.find_and_ref_up(Term::Ap(l, x))
+			},
+			Term::Abs(v, term) => {
+				let x = self.step(term, TraversalOrder::LeftmostOutermost);
+				if x != term {
+					return self.find_and_ref_up(Term::Abs(v, x));
+				}
+				panic!("WTF MANG");
+			}
+			Term::Lam(term) => {
+				let x = self.step(term, TraversalOrder::LeftmostOutermost);
+				self.find_and_ref_up(Term::Lam(x))
+			}
+		}
+	}
+
+	pub fn innermost_leftmost(&mut self, key: usize) -> usize {
+		if self.normal_form(key) {
+			return key;
+		}
+		match self.slab[key].term {
+			Term::Ap(l, r) => {
+				if !self.normal_form(l) {
+					let x = self.step(l, TraversalOrder::LeftmostInnermost);
+					return self.find_and_ref_up(Term::Ap(x, r));
+				}
+				if !self.normal_form(r) {
+					let x = self.step(r, TraversalOrder::LeftmostInnermost);
+					return self.find_and_ref_up(Term::Ap(l, x));
+				}
+			},
+			Term::Abs(v, term) => {
+				if !self.normal_form(term) {
+					let x = self.step(term, TraversalOrder::LeftmostInnermost);
+					return self.find_and_ref_up(Term::Abs(v, x));
+				}
+			},
+			Term::Lam(term) => {
+				let x = self.step(term, TraversalOrder::LeftmostInnermost);
+				return self.find_and_ref_up(Term::Lam(x));
+			},
+			Term::Const(_) => (), // unfolds below
+			Term::I |
+			Term::K |
+			Term::S |
+			Term::B |
+			Term::C |
+			Term::W |
+			Term::X |
+			Term::Bulk(_, _) |
+			Term::Comb(_) |
+			Term::Idx(_) |
+			Term::Var(_) => panic!("normal form didnt catch"),
+		}
+		self.root_rewrite(key)
+	}
+
+	pub fn outermost_rightmost(&mut self, key: usize) -> usize {
+		if self.normal_form(key) {
+			return key;
+		}
+		// a redex may rewrite to itself, as in (λx.xx)(λx.xx)
+		if self.root_can_rewrite(key) {
+			return self.root_rewrite(key);
+		}
+		match self.slab[key].term {
+			Term::Ap(l, r) => {
+				if !self.normal_form(r) {
+					let x = self.step(r, TraversalOrder::RightmostOutermost);
+					return self.find_and_ref_up(Term::Ap(l, x));
+				}
+				let x = self.step(l, TraversalOrder::RightmostOutermost);
+				self.find_and_ref_up(Term::Ap(x, r))
+			},
+			Term::Abs(v, term) => {
+				let x = self.step(term, TraversalOrder::RightmostOutermost);
+				self.find_and_ref_up(Term::Abs(v, x))
+			},
+			Term::Lam(term) => {
+				let x = self.step(term, TraversalOrder::RightmostOutermost);
+				self.find_and_ref_up(Term::Lam(x))
+			},
+			Term::I |
+			Term::K |
+			Term::S |
+			Term::B |
+			Term::C |
+			Term::W |
+			Term::X |
+			Term::Bulk(_, _) |
+			Term::Comb(_) |
+			Term::Idx(_) |
+			Term::Const(_) |
+			Term::Var(_) => panic!("normal form didnt catch"),
+		}
+	}
+
+	// Gross-Knuth step: contracts every outermost redex at once
+	pub fn outermost_parallel(&mut self, key: usize) -> usize {
+		if self.normal_form(key) {
+			return key;
+		}
+		// a redex may rewrite to itself, as in (λx.xx)(λx.xx)
+		if self.root_can_rewrite(key) {
+			return self.root_rewrite(key);
+		}
+		match self.slab[key].term {
+			Term::Ap(l, r) => {
+				let l = self.step(l, TraversalOrder::ParallelOutermost);
+				let r = self.step(r, TraversalOrder::ParallelOutermost);
+				self.find_and_ref_up(Term::Ap(l, r))
+			},
+			Term::Abs(v, term) => {
+				let x = self.step(term, TraversalOrder::ParallelOutermost);
+				self.find_and_ref_up(Term::Abs(v, x))
+			},
+			Term::Lam(term) => {
+				let x = self.step(term, TraversalOrder::ParallelOutermost);
+				self.find_and_ref_up(Term::Lam(x))
+			},
+			Term::I |
+			Term::K |
+			Term::S |
+			Term::B |
+			Term::C |
+			Term::W |
+			Term::X |
+			Term::Bulk(_, _) |
+			Term::Comb(_) |
+			Term::Idx(_) |
+			Term::Const(_) |
+			Term::Var(_) => panic!("normal form didnt catch"),
+		}
+	}
+
+	// one rewrite step of the given strategy. Normal forms are returned unchanged.
+	// Memoized normal forms are left to `reduce`, so a step is always one rewrite
+	pub fn step(&mut self, key: usize, order: TraversalOrder) -> usize {
+		let next = match self.slab[key].successor {
+			Some((o, next)) if o == order => next,
+			_ => {
+				let next = match order {
+					TraversalOrder::LeftmostOutermost => self.outermost_leftmost(key),
+					TraversalOrder::LeftmostInnermost => self.innermost_leftmost(key),
+					TraversalOrder::RightmostOutermost => self.outermost_rightmost(key),
+					TraversalOrder::ParallelOutermost => self.outermost_parallel(key),
+				};
+				self.slab[key].successor = Some((order, next));
+				next
+			},
+		};
+		// shortcut chains of successors once their normal form is known
+		if let Some(nf) = self.memoized_normal_form(next, order) {
+			self.slab[key].normal_form = Some((order, nf));
+		} else if next != key && self.normal_form(next) {
+			self.slab[key].normal_form = Some((order, next));
+		}
+		next
+	}
+
+	// the normal form `order` reaches from `key`, if known. One found by another strategy
+	// only counts for a normalizing one, as `order` itself might never get there
+	pub fn memoized_normal_form(&self, key: usize, order: TraversalOrder) -> Option<usize> {
+		match self.slab[key].normal_form {
+			Some((o, nf)) if o == order || order.is_normalizing() => Some(nf),
+			_ => None,
+		}
+	}
+
+	pub(crate) fn memoize_normal_form(&mut self, key: usize, order: TraversalOrder, nf: usize) {
+		self.slab[key].normal_form = Some((order, nf));
+	}
+
+	/////////////////// garbage collection
+
+	// returns the number of reclaimed nodes
+	pub fn gc_all_but_defined(&mut self) -> usize {
+		self.gc_all_but_defined_and(None.into_iter())
+	}
+
+	// returns the number of reclaimed nodes
+	pub fn gc_all_but_defined_and(&mut self, roots: impl Iterator<Item=usize>) -> usize {
+		let mut x: Vec<_> = self.defined.values().chain(self.unfolded.values()).cloned().collect();
+		x.extend(roots);
+		self.gc_all_but(x.into_iter())
+	}
+
+	// returns the number of reclaimed nodes
+	pub fn gc_all_but(&mut self, roots: impl Iterator<Item=usize>) -> usize {
+		let mut live = FnvHashSet::default();
+		let mut stack: Vec<usize> = roots.collect();
+		stack.push(self.i_key);
+		stack.push(self.k_key);
+		stack.push(self.s_key);
+		stack.push(self.b_key);
+		stack.push(self.c_key);
+		stack.push(self.w_key);
+		stack.push(self.x_key);
+		stack.extend(self.rules.iter().map(|rule| rule.rhs));
+		while let Some(key) = stack.pop() {
+			if !live.insert(key) {
+				continue; // shared subterm, already marked
+			}
+			match self.slab[key].term {
+				Term::Ap(l, r) => {
+					stack.push(l);
+					stack.push(r);
+				},
+				Term::Abs(_, body) |
+				Term::Lam(body) => stack.push(body),
+				Term::I |
+				Term::K |
+				Term::S |
+				Term::B |
+				Term::C |
+				Term::W |
+				Term::X |
+				Term::Bulk(_, _) |
+				Term::Comb(_) |
+				Term::Idx(_) |
+				Term::Const(_) |
+				Term::Var(_) => (),
+			}
+		}
+		let dead: Vec<usize> = self.slab.iter()
+		.map(|(key, _)| key)
+		.filter(|key| !live.contains(key))
+		.collect();
+		for &key in dead.iter() {
+			let entry = self.slab.remove(key);
+			self.slab_locator.remove(&entry.term);
+		}
+		for (_, entry) in self.slab.iter_mut() {
+			if entry.successor.is_some_and(|(_, k)| !live.contains(&k)) {
+				entry.successor = None;
+			}
+			if entry.normal_form.is_some_and(|(_, k)| !live.contains(&k)) {
+				entry.normal_form = None;
+			}
+		}
+		self.collections += 1;
+		debug_assert_eq!(self.check_invariants(), Ok(()));
+		dead.len()
+	}
+
+	// the locator is consistent, every child, memo and root is a live node,
+	// and the primitive keys still hold their combinators
+	pub fn check_invariants(&self) -> Result<(), String> {
+		self.check_locator()?;
+		for (key, entry) in self.slab.iter() {
+			let children = match entry.term {
+				Term::Ap(l, r) => vec![l, r],
+				Term::Abs(_, body) |
+				Term::Lam(body) => vec![body],
+				Term::I |
+				Term::K |
+				Term::S |
+				Term::B |
+				Term::C |
+				Term::W |
+				Term::X |
+				Term::Bulk(_, _) |
+				Term::Comb(_) |
+				Term::Idx(_) |
+				Term::Const(_) |
+				Term::Var(_) => vec![],
+			};
+			for child in children {
+				if !self.slab.contains(child) {
+					return Err(format!("{:?} at {} has vacant child {}", entry.term, key, child));
+				}
+			}
+			let memos = entry.successor.map(|(_, k)| k).into_iter().chain(entry.normal_form.map(|(_, k)| k));
+			for memo in memos {
+				if !self.slab.contains(memo) {
+					return Err(format!("{:?} at {} remembers vacant {}", entry.term, key, memo));
+				}
+			}
+		}
+		let primitives = [
+			(self.i_key, Term::I),
+			(self.k_key, Term::K),
+			(self.s_key, Term::S),
+			(self.b_key, Term::B),
+			(self.c_key, Term::C),
+			(self.w_key, Term::W),
+			(self.x_key, Term::X),
+		];
+		for &(key, term) in primitives.iter() {
+			if self.slab.get(key).map(|e| e.term) != Some(term) {
+				return Err(format!("{:?} is no longer at {}", term, key));
+			}
+		}
+		for (&name, &key) in self.defined.iter() {
+			if !self.slab.contains(key) {
+				return Err(format!("definition {} refers to vacant {}", self.symbols.name(name), key));
+			}
+		}
+		Ok(())
+	}
+}
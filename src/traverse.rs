@@ -0,0 +1,108 @@
+use std::fmt;
+use std::str::FromStr;
+
+use term::{Term, TermBase};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TraversalOrder {
+	LeftmostOutermost,
+	LeftmostInnermost,  // applicative order
+	RightmostOutermost,
+	ParallelOutermost,  // Gross-Knuth
+}
+impl TraversalOrder {
+	pub const ALL: [TraversalOrder; 4] = [
+		TraversalOrder::LeftmostOutermost,
+		TraversalOrder::LeftmostInnermost,
+		TraversalOrder::RightmostOutermost,
+		TraversalOrder::ParallelOutermost,
+	];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			TraversalOrder::LeftmostOutermost => "lo",
+			TraversalOrder::LeftmostInnermost => "li",
+			TraversalOrder::RightmostOutermost => "ro",
+			TraversalOrder::ParallelOutermost => "po",
+		}
+	}
+
+	// reaches the normal form of every term that has one
+	pub fn is_normalizing(self) -> bool {
+		self == TraversalOrder::LeftmostOutermost || self == TraversalOrder::ParallelOutermost
+	}
+}
+impl fmt::Display for TraversalOrder {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
+impl FromStr for TraversalOrder {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		TraversalOrder::ALL.iter()
+		.cloned()
+		.find(|o| o.name() == s || format!("{:?}", o).eq_ignore_ascii_case(s))
+		.ok_or_else(|| format!("unknown traversal order `{}`, expected one of lo, li, ro, po", s))
+	}
+}
+
+pub struct Traverser<'a> {
+	tb: &'a TermBase,
+	traversal_order: TraversalOrder,
+	stack: Vec<(usize, bool)>, // (key, children already pushed)
+}
+impl<'a> Traverser<'a> {
+	pub fn new(tb: &'a TermBase, traversal_order: TraversalOrder, root: usize) -> Traverser<'a> {
+		Traverser {
+			tb,
+			traversal_order,
+			stack: vec![(root, false)],
+		}
+	}
+}
+
+impl<'a> Iterator for Traverser<'a>{
+    type Item = usize;
+    fn next(&mut self) -> Option<Self::Item> {
+    	loop {
+	    	let (key, expanded) = self.stack.pop()?;
+	    	if expanded {
+	    		return Some(key);
+	    	}
+	    	let children = match self.tb.term(key) {
+	        	Term::Ap(l, r) => {
+	        		match self.traversal_order {
+	        			TraversalOrder::RightmostOutermost => [Some(l), Some(r)],
+	        			_ => [Some(r), Some(l)],
+	        		}
+	        	},
+	        	Term::Abs(_, k) |
+	        	Term::Lam(k) => [None, Some(k)],
+	        	Term::I |
+	        	Term::K |
+	        	Term::S |
+	        	Term::B |
+	        	Term::C |
+	        	Term::W |
+	        	Term::X |
+	        	Term::Bulk(_, _) |
+	        	Term::Comb(_) |
+	        	Term::Idx(_) |
+	        	Term::Const(_) |
+	        	Term::Var(_) => [None, None],
+	        };
+	        match self.traversal_order {
+	        	TraversalOrder::LeftmostInnermost => {
+	        		// children first
+	        		self.stack.push((key, true));
+	        		self.stack.extend(children.iter().filter_map(|&c| c).map(|c| (c, false)));
+	        	},
+	        	_ => {
+	        		self.stack.extend(children.iter().filter_map(|&c| c).map(|c| (c, false)));
+	        		return Some(key);
+	        	},
+	        }
+	    }
+    }
+}
@@ -0,0 +1,368 @@
+use fnv::FnvHashMap;
+
+use std::fmt;
+
+use bulk::Bulk;
+use symbol::Sym;
+use term::{Term, TermBase};
+
+// A simple type, its variables numbered
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+	Var(u32),
+	Arrow(Box<Type>, Box<Type>),
+}
+impl Type {
+	fn arrow(a: Type, b: Type) -> Type {
+		Type::Arrow(Box::new(a), Box::new(b))
+	}
+
+	// a1 → .. → an → r
+	fn arrows(args: Vec<Type>, r: Type) -> Type {
+		args.into_iter().rev().fold(r, |r, a| Type::arrow(a, r))
+	}
+
+	fn write(&self, names: &mut Vec<u32>, out: &mut String) {
+		match self {
+			Type::Var(v) => {
+				let i = names.iter().position(|w| w == v).unwrap_or_else(|| {
+					names.push(*v);
+					names.len() - 1
+				});
+				out.push_str(&type_var_name(i));
+			},
+			Type::Arrow(a, b) => {
+				if let Type::Arrow(_, _) = **a {
+					out.push('(');
+					a.write(names, out);
+					out.push(')');
+				} else {
+					a.write(names, out);
+				}
+				out.push('→');
+				b.write(names, out);
+			},
+		}
+	}
+}
+impl fmt::Display for Type {
+	// variables named a, b, c, .. in order of appearance
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let mut out = String::new();
+		self.write(&mut vec![], &mut out);
+		write!(f, "{}", out)
+	}
+}
+
+// `a would equal b`, naming the variables of both alike
+fn mismatch(a: &Type, b: &Type) -> String {
+	let mut names = vec![];
+	let mut out = String::new();
+	a.write(&mut names, &mut out);
+	out.push_str(" would equal ");
+	b.write(&mut names, &mut out);
+	out
+}
+
+fn type_var_name(i: usize) -> String {
+	let letter = (b'a' + (i % 26) as u8) as char;
+	match i / 26 {
+		0 => letter.to_string(),
+		n => format!("{}{}", letter, n),
+	}
+}
+
+// Hindley-Milner inference, with free variables monomorphic and definitions and rules
+// polymorphic except within their own recursion
+struct Infer {
+	subst: Vec<Option<Type>>,          // what each type variable was unified with
+	vars: FnvHashMap<Sym, Type>,       // term variables, free or bound by Abs
+	env: Vec<Type>,                    // types of the enclosing Lam binders, innermost last
+	in_progress: Vec<(Term, Type)>,    // definitions and rules being inferred
+}
+impl Infer {
+	fn fresh(&mut self) -> Type {
+		self.subst.push(None);
+		Type::Var(self.subst.len() as u32 - 1)
+	}
+
+	// `t` with every bound variable replaced
+	fn resolve(&self, t: &Type) -> Type {
+		match t {
+			Type::Var(v) => match &self.subst[*v as usize] {
+				Some(t) => self.resolve(t),
+				None => t.clone(),
+			},
+			Type::Arrow(a, b) => Type::arrow(self.resolve(a), self.resolve(b)),
+		}
+	}
+
+	fn occurs(&self, v: u32, t: &Type) -> bool {
+		match t {
+			Type::Var(w) => match &self.subst[*w as usize] {
+				Some(t) => self.occurs(v, t),
+				None => v == *w,
+			},
+			Type::Arrow(a, b) => self.occurs(v, a) || self.occurs(v, b),
+		}
+	}
+
+	// fails with the two types that cannot be equal
+	fn unify(&mut self, a: &Type, b: &Type) -> Result<(), (Type, Type)> {
+		let (a, b) = (self.shallow(a), self.shallow(b));
+		match (&a, &b) {
+			(Type::Var(v), Type::Var(w)) if v == w => Ok(()),
+			(Type::Var(v), t) |
+			(t, Type::Var(v)) => {
+				if self.occurs(*v, t) {
+					return Err((self.resolve(&a), self.resolve(&b)));
+				}
+				self.subst[*v as usize] = Some(t.clone());
+				Ok(())
+			},
+			(Type::Arrow(a1, a2), Type::Arrow(b1, b2)) => {
+				self.unify(a1, b1)?;
+				self.unify(a2, b2)
+			},
+		}
+	}
+
+	// `t`, or what it was unified with if a variable
+	fn shallow(&self, t: &Type) -> Type {
+		match t {
+			Type::Var(v) => match &self.subst[*v as usize] {
+				Some(t) => self.shallow(t),
+				None => t.clone(),
+			},
+			_ => t.clone(),
+		}
+	}
+
+	// a copy of `t` with fresh variables
+	fn instantiate(&mut self, t: &Type) -> Type {
+		let mut renamed = FnvHashMap::default();
+		self.instantiate_rec(t, &mut renamed)
+	}
+
+	fn instantiate_rec(&mut self, t: &Type, renamed: &mut FnvHashMap<u32, Type>) -> Type {
+		match t {
+			Type::Var(v) => {
+				if let Some(t) = renamed.get(v) {
+					return t.clone();
+				}
+				let fresh = self.fresh();
+				renamed.insert(*v, fresh.clone());
+				fresh
+			},
+			Type::Arrow(a, b) => {
+				let a = self.instantiate_rec(a, renamed);
+				let b = self.instantiate_rec(b, renamed);
+				Type::arrow(a, b)
+			},
+		}
+	}
+
+	// the principal type of a primitive, with fresh variables
+	fn primitive(&mut self, t: Term) -> Type {
+		let mut v = vec![];
+		for _ in 0..3 {
+			v.push(self.fresh());
+		}
+		let (a, b, c) = (v[0].clone(), v[1].clone(), v[2].clone());
+		match t {
+			Term::I => Type::arrow(a.clone(), a),
+			Term::K => Type::arrows(vec![a.clone(), b], a),
+			Term::S => Type::arrows(vec![
+				Type::arrows(vec![a.clone(), b.clone()], c.clone()),
+				Type::arrow(a.clone(), b),
+				a,
+			], c),
+			Term::B => Type::arrows(vec![
+				Type::arrow(b.clone(), c.clone()),
+				Type::arrow(a.clone(), b),
+				a,
+			], c),
+			Term::C => Type::arrows(vec![
+				Type::arrows(vec![a.clone(), b.clone()], c.clone()),
+				b,
+				a,
+			], c),
+			Term::W => Type::arrows(vec![
+				Type::arrows(vec![a.clone(), a.clone()], b.clone()),
+				a,
+			], b),
+			// X f = f S K
+			Term::X => {
+				let s = self.primitive(Term::S);
+				let k = self.primitive(Term::K);
+				Type::arrow(Type::arrows(vec![s, k], a.clone()), a)
+			},
+			Term::Bulk(kind, n) => {
+				let xs: Vec<Type> = (0..n).map(|_| self.fresh()).collect();
+				match kind {
+					// (a → b) → (x1 → .. → xn → a) → x1 → .. → xn → b
+					Bulk::B => Type::arrows(vec![
+						Type::arrow(a.clone(), b.clone()),
+						Type::arrows(xs.clone(), a),
+					], Type::arrows(xs, b)),
+					// (x1 → .. → xn → a → b) → a → x1 → .. → xn → b
+					Bulk::C => {
+						let f = Type::arrows(xs.clone(), Type::arrow(a.clone(), b.clone()));
+						Type::arrows(vec![f, a], Type::arrows(xs, b))
+					},
+					// (x1 → .. → xn → a → b) → (x1 → .. → xn → a) → x1 → .. → xn → b
+					Bulk::S => {
+						let f = Type::arrows(xs.clone(), Type::arrow(a.clone(), b.clone()));
+						let g = Type::arrows(xs.clone(), a);
+						Type::arrows(vec![f, g], Type::arrows(xs, b))
+					},
+				}
+			},
+			_ => unreachable!(),
+		}
+	}
+}
+
+impl TermBase {
+	// the principal simple type of `key`, or why it has none.
+	// Free variables are typed too, the same wherever they occur
+	pub fn principal_type(&mut self, key: usize) -> Result<Type, String> {
+		let mut infer = Infer {
+			subst: vec![],
+			vars: FnvHashMap::default(),
+			env: vec![],
+			in_progress: vec![],
+		};
+		let (t, _) = self.infer(key, &mut infer)?;
+		Ok(infer.resolve(&t))
+	}
+
+	// the type of `key`, and whether it is closed, so its principal type can be remembered
+	fn infer(&mut self, key: usize, infer: &mut Infer) -> Result<(Type, bool), String> {
+		if let Some(t) = self.principal(key) {
+			let t = t.clone();
+			return Ok((infer.instantiate(&t), true));
+		}
+		let (t, closed) = match self.term(key) {
+			Term::Var(v) => {
+				let t = match infer.vars.get(&v) {
+					Some(t) => t.clone(),
+					None => {
+						let t = infer.fresh();
+						infer.vars.insert(v, t.clone());
+						t
+					},
+				};
+				(t, false)
+			},
+			Term::Idx(i) => match infer.env.len().checked_sub(i as usize + 1) {
+				Some(at) => (infer.env[at].clone(), false),
+				None => return Err(format!("index #{} is bound outside the term", i)),
+			},
+			Term::Ap(l, r) => {
+				let (tl, cl) = self.infer(l, infer)?;
+				let (tr, cr) = self.infer(r, infer)?;
+				let result = infer.fresh();
+				if let Err((a, b)) = infer.unify(&tl, &Type::arrow(tr, result.clone())) {
+					return Err(format!("`{}` has no simple type, as {}", self.term_string(key), mismatch(&a, &b)));
+				}
+				(result, cl && cr)
+			},
+			Term::Abs(v, body) => {
+				let a = infer.fresh();
+				let shadowed = infer.vars.insert(v, a.clone());
+				let body = self.infer(body, infer);
+				match shadowed {
+					Some(t) => infer.vars.insert(v, t),
+					None => infer.vars.remove(&v),
+				};
+				(Type::arrow(a, body?.0), false)
+			},
+			Term::Lam(body) => {
+				let a = infer.fresh();
+				infer.env.push(a.clone());
+				let body = self.infer(body, infer);
+				infer.env.pop();
+				(Type::arrow(a, body?.0), false)
+			},
+			t @ Term::Const(_) |
+			t @ Term::Comb(_) => (self.infer_named(t, infer)?, true),
+			t @ Term::I |
+			t @ Term::K |
+			t @ Term::S |
+			t @ Term::B |
+			t @ Term::C |
+			t @ Term::W |
+			t @ Term::X |
+			t @ Term::Bulk(_, _) => (infer.primitive(t), true),
+		};
+		if closed && infer.in_progress.is_empty() {
+			let principal = infer.resolve(&t);
+			self.set_principal(key, principal);
+		}
+		Ok((t, closed))
+	}
+
+	// a definition or rule, monomorphic where it refers to itself
+	fn infer_named(&mut self, named: Term, infer: &mut Infer) -> Result<Type, String> {
+		if let Some((_, t)) = infer.in_progress.iter().find(|&&(n, _)| n == named) {
+			return Ok(t.clone());
+		}
+		let own = infer.fresh();
+		infer.in_progress.push((named, own.clone()));
+		let t = match named {
+			Term::Const(name) => match self.definition(self.name(name)) {
+				Some(def) => self.infer(def, infer).map(|(t, _)| t),
+				None => Err(format!("`{}` is not defined", self.name(name))),
+			},
+			Term::Comb(id) => {
+				let rule = self.rules[id as usize].clone();
+				let outer = std::mem::take(&mut infer.vars);
+				let params: Vec<Type> = rule.params.iter().map(|&v| {
+					let t = infer.fresh();
+					infer.vars.insert(v, t.clone());
+					t
+				}).collect();
+				let rhs = self.infer(rule.rhs, infer);
+				infer.vars = outer;
+				rhs.map(|(t, _)| Type::arrows(params, t))
+			},
+			_ => unreachable!(),
+		};
+		infer.in_progress.pop();
+		let t = t?;
+		if let Err((a, b)) = infer.unify(&own, &t) {
+			let name = match named {
+				Term::Const(name) => self.name(name),
+				Term::Comb(id) => self.rule_name(id),
+				_ => unreachable!(),
+			};
+			return Err(format!("`{}` has no simple type, as {}", name, mismatch(&a, &b)));
+		}
+		if infer.in_progress.is_empty() {
+			let t = infer.resolve(&t);
+			Ok(infer.instantiate(&t))
+		} else {
+			Ok(t)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn principal_type_of_s() {
+		let mut tb = TermBase::new();
+		let s = tb.parse("S").unwrap();
+		assert_eq!(tb.principal_type(s).unwrap().to_string(), "(a→b→c)→(a→b)→a→c");
+	}
+
+	#[test]
+	fn self_application_fails_the_occurs_check() {
+		let mut tb = TermBase::new();
+		let sii = tb.parse("SII").unwrap();
+		assert_eq!(tb.principal_type(sii), Err("`SII` has no simple type, as a would equal a→b".to_owned()));
+	}
+}